@@ -1,9 +1,9 @@
 #![no_main]
 #![no_std]
 
+use core::cell::Cell;
 use core::ffi;
 use core::fmt::Write as _;
-use core::mem;
 use core::ptr::NonNull;
 
 use aarch64_cpu::registers::CNTHCTL_EL2;
@@ -15,10 +15,16 @@ use aarch64_cpu::registers::SCR_EL3;
 use aarch64_cpu::registers::SPSR_EL2;
 use aarch64_cpu::registers::SPSR_EL3;
 use elf::endian::AnyEndian;
+use kernel_core::device::bcm2837b0::MMIO;
 use kernel_core::device::bcm2837b0::gpio;
 use kernel_core::device::bcm2837b0::mini;
+use kernel_core::mem::Kernel;
+use kernel_core::mem::PAGE_SIZE;
 use kernel_core::mem::Phys;
+use kernel_core::mem::User;
 use kernel_core::mem::Virt;
+use kernel_core::mmu;
+use kernel_core::mmu::PageTable;
 use tock_registers::interfaces::Readable as _;
 use tock_registers::interfaces::Writeable as _;
 
@@ -68,6 +74,10 @@ _start:
 ",
 }
 
+unsafe extern "C" {
+    static __TEXT_HI: ffi::c_void;
+}
+
 #[unsafe(link_section = ".text.start")]
 #[unsafe(no_mangle)]
 pub extern "C" fn _start_hypervisor(
@@ -163,18 +173,11 @@ fn _start_kernel(device_tree: u64) -> ! {
         uart.write_byte(byte);
     }
 
-    let mut buffer = [0u8; 8];
-    buffer.iter_mut().for_each(|byte| *byte = uart.read_byte());
-
-    let len = u64::from_le_bytes(buffer) as usize;
-    let base = (1 << 29) as *mut u8;
-
-    for i in 0..len {
-        let byte = uart.read_byte();
-        unsafe {
-            base.add(i).write_volatile(byte);
-        }
-    }
+    // Stage ELF file directly after our own image, which keeps it within
+    // the ARM memory split of 512MiB boards
+    let base = (unsafe { &__TEXT_HI } as *const _ as usize).next_multiple_of(PAGE_SIZE as usize)
+        as *mut u8;
+    let len = receive(&mut uart, base);
 
     writeln!(
         &mut uart,
@@ -184,6 +187,18 @@ fn _start_kernel(device_tree: u64) -> ! {
     )
     .unwrap();
 
+    // Followed by the initial ramdisk, which is empty if none was sent
+    let initrd_src = unsafe { base.add(len.next_multiple_of(PAGE_SIZE as usize)) };
+    let initrd_len = receive(&mut uart, initrd_src);
+
+    writeln!(
+        &mut uart,
+        "[PULL] Wrote initrd ({:#x?}) at {:#x?}",
+        kernel_core::unit::Byte::new(initrd_len),
+        initrd_src,
+    )
+    .unwrap();
+
     let elf = elf::ElfBytes::<AnyEndian>::minimal_parse(unsafe {
         core::slice::from_raw_parts(base, len)
     })
@@ -194,7 +209,7 @@ fn _start_kernel(device_tree: u64) -> ! {
     let heap = segments
         .iter()
         .map(|segment| segment.p_paddr + segment.p_memsz)
-        .map(|address| address.next_multiple_of(1 << 16))
+        .map(|address| address.next_multiple_of(PAGE_SIZE))
         .max()
         .unwrap();
 
@@ -204,30 +219,12 @@ fn _start_kernel(device_tree: u64) -> ! {
         .map(|segment| segment.p_vaddr - segment.p_paddr)
         .unwrap();
 
-    let page_table_len =
-        mem::size_of::<kernel_core::mmu::PageTable<kernel_core::mem::Kernel>>() as u64;
-    let page_table_kernel = unsafe {
-        (heap as *mut kernel_core::mmu::PageTable<kernel_core::mem::Kernel>)
-            .as_mut()
-            .unwrap()
-    };
-
-    let device_tree_len = unsafe {
-        device_tree::Blob::from_ptr(
-            NonNull::new(device_tree as *mut device_tree::blob::Header).unwrap(),
-        )
-    }
-    .header()
-    .len();
+    let device_tree_len =
+        unsafe { device_tree::Blob::from_ptr(NonNull::new(device_tree as *mut u8).unwrap()) }
+            .header()
+            .len();
     let device_tree_src = device_tree;
-    let device_tree_dst = (heap + page_table_len).next_multiple_of(1 << 16);
-
-    let page_table_identity = unsafe {
-        ((device_tree_dst + device_tree_len as u64).next_multiple_of(1 << 16)
-            as *mut kernel_core::mmu::PageTable<kernel_core::mem::User>)
-            .as_mut()
-            .unwrap()
-    };
+    let device_tree_dst = heap;
 
     writeln!(
         &mut uart,
@@ -246,37 +243,89 @@ fn _start_kernel(device_tree: u64) -> ! {
         )
     };
 
-    kernel_core::mmu::init();
+    // Initial ramdisk directly after the device tree, where the kernel
+    // reserves it
+    let initrd_dst = (device_tree_dst + device_tree_len as u64).next_multiple_of(PAGE_SIZE);
+    let initrd = initrd_dst..initrd_dst + initrd_len as u64;
 
     writeln!(
         &mut uart,
-        "[PULL] Initializing kernel page table at {:#x?} (offset {:#x})",
-        page_table_kernel as *mut _, offset
+        "[PULL] Relocating initrd ({:#x?}) from {:#x?} to {:#x}",
+        kernel_core::unit::Byte::new(initrd_len),
+        initrd_src,
+        initrd_dst,
     )
     .unwrap();
 
-    page_table_kernel.init(offset);
+    unsafe { core::ptr::copy(initrd_src, initrd_dst as *mut u8, initrd_len) };
+
+    let memory = kernel_core::mem::memory(&unsafe {
+        device_tree::Blob::from_ptr(NonNull::new(device_tree_dst as *mut u8).unwrap())
+    });
+
+    let config = mmu::Config::DEFAULT;
+    mmu::init(&config);
+
+    // Page table frames are handed off to the kernel, which must reserve them
+    let tables = Bump::new(initrd.end.next_multiple_of(PAGE_SIZE));
+
+    let mut page_table_kernel = PageTable::<Kernel>::new(config, 0, &tables).unwrap();
+    let mut page_table_identity = PageTable::<User>::new(config, 0, &tables).unwrap();
 
     writeln!(
         &mut uart,
-        "[PULL] Initializing identity page table at {:#x?} (offset {:#x})",
-        page_table_identity as *mut _, 0
+        "[PULL] Initializing page tables at {:#x?} (kernel) and {:#x?} (identity) with {:?}",
+        page_table_kernel.root(),
+        page_table_identity.root(),
+        config,
     )
     .unwrap();
 
-    page_table_identity.init(0);
-    // NOTE: identity table is used only for bootstrapping and can be clobbered
-    // Do not need to reserve in page allocator
+    assert_eq!(
+        offset,
+        kernel_core::mem::OFFSET,
+        "Kernel linked at unexpected offset"
+    );
+
+    // Linear map of RAM, which also covers page table frames for the kernel
+    let granule = config.granule.size();
+    for range in &memory {
+        writeln!(
+            &mut uart,
+            "[PULL] Mapping memory {:#x} - {:#x} at {:#x}",
+            u64::from(range.start),
+            u64::from(range.end),
+            u64::from(range.start.to_virt()),
+        )
+        .unwrap();
+    }
+
+    page_table_kernel.map_linear(&tables, memory.iter().cloned());
+
+    // Device MMIO is identity mapped for the kernel's entry point, and in the
+    // linear map for the kernel to use once it unmaps the identity table
+    page_table_kernel.map_range(
+        &tables,
+        Virt::new(MMIO.start + offset),
+        Phys::new(MMIO.start),
+        MMIO.end - MMIO.start,
+        mmu::Attr::Device,
+    );
+    page_table_identity.map_range(
+        &tables,
+        Virt::new(MMIO.start),
+        Phys::new(MMIO.start),
+        MMIO.end - MMIO.start,
+        mmu::Attr::Device,
+    );
 
     // Map device tree
-    page_table_kernel.map(
+    page_table_kernel.map_range(
+        &tables,
         Virt::new(device_tree_dst + offset),
         Phys::new(device_tree_dst),
-        kernel_core::mmu::Attr::Normal {
-            read: true,
-            write: false,
-            execute: false,
-        },
+        (device_tree_len as u64).next_multiple_of(granule),
+        mmu::Attr::kernel(true, false, false),
     );
 
     // Load kernel binary
@@ -289,11 +338,7 @@ fn _start_kernel(device_tree: u64) -> ! {
         let write = segment.p_flags & elf::abi::PF_W > 0;
         let execute = segment.p_flags & elf::abi::PF_X > 0;
         let data = elf.segment_data(&segment).unwrap().as_ptr();
-        let attr = kernel_core::mmu::Attr::Normal {
-            read,
-            write,
-            execute,
-        };
+        let attr = mmu::Attr::kernel(read, write, execute);
 
         writeln!(
             &mut uart,
@@ -324,31 +369,37 @@ fn _start_kernel(device_tree: u64) -> ! {
 
         // Only need to identity map first page of executable to bootstrap
         if execute {
-            map(
-                page_table_identity,
-                0,
-                segment.p_paddr,
-                segment.p_memsz.min(1 << 16),
+            page_table_identity.map(
+                &tables,
+                Virt::new(segment.p_paddr),
+                Phys::new(segment.p_paddr),
                 attr,
             );
         }
 
-        map(
-            page_table_kernel,
-            offset,
-            segment.p_paddr,
-            segment.p_memsz,
+        page_table_kernel.map_range(
+            &tables,
+            Virt::new(segment.p_paddr + offset),
+            Phys::new(segment.p_paddr),
+            segment.p_memsz.next_multiple_of(granule),
             attr,
         );
     }
 
+    page_table_kernel.install();
+    page_table_identity.install();
+
+    let tables = tables.start..tables.next.get();
+
     writeln!(
         &mut uart,
-        "[PULL] Calling kernel at {:#x} with device_tree={:#x}, page_table_kernel={:#x}, heap={:#x}",
+        "[PULL] Calling kernel at {:#x} with device_tree={:#x}, tables={:#x}..{:#x}, initrd={:#x}..{:#x}",
         elf.ehdr.e_entry - offset,
         device_tree_dst + offset,
-        page_table_kernel as *mut _ as u64 + offset,
-        page_table_identity as *mut _ as u64 + offset,
+        tables.start,
+        tables.end,
+        initrd.start,
+        initrd.end,
     )
     .unwrap();
 
@@ -356,32 +407,65 @@ fn _start_kernel(device_tree: u64) -> ! {
 
     unsafe {
         core::arch::asm! {
-            "mov x0, {arg_0:x}",
-            "mov x1, {arg_1:x}",
-            "mov x2, {arg_2:x}",
             "br {entry:x}",
-            arg_0 = in(reg) device_tree_dst + offset,
-            arg_1 = in(reg) page_table_kernel as *mut _ as u64 + offset,
-            arg_2 = in(reg) (device_tree_dst + device_tree_len as u64 + offset).next_multiple_of(1 << 16),
+            in("x0") device_tree_dst + offset,
+            in("x1") tables.start,
+            in("x2") tables.end,
+            in("x3") initrd.start,
+            in("x4") initrd.end,
             entry = in(reg) elf.ehdr.e_entry - offset,
             options(nomem, noreturn)
         }
     }
 }
 
-fn map<S: kernel_core::mem::AddressSpace>(
-    page_table: &mut kernel_core::mmu::PageTable<S>,
-    offset: u64,
-    phys: u64,
-    len: u64,
-    attr: kernel_core::mmu::Attr,
-) {
-    (0..len)
-        .step_by(1 << 16)
-        .map(|page| phys + page)
-        .map(|phys| (phys + offset, phys))
-        .map(|(virt, phys)| (Virt::new(virt), Phys::new(phys)))
-        .for_each(|(virt, phys)| page_table.map(virt, phys, attr))
+/// Receive a little-endian `u64` length followed by that many bytes into
+/// `base`, returning the length.
+fn receive(uart: &mut mini::Uart, base: *mut u8) -> usize {
+    let mut buffer = [0u8; 8];
+    buffer.iter_mut().for_each(|byte| *byte = uart.read_byte());
+
+    let len = u64::from_le_bytes(buffer) as usize;
+    for i in 0..len {
+        let byte = uart.read_byte();
+        unsafe {
+            base.add(i).write_volatile(byte);
+        }
+    }
+
+    len
+}
+
+/// Bump allocator for page table frames, which are never freed before
+/// handing off to the kernel.
+struct Bump {
+    start: u64,
+    next: Cell<u64>,
+}
+
+impl Bump {
+    /// Upper bound on page table frames, to catch runaway allocation.
+    const LEN: u64 = 1 << 22;
+
+    fn new(start: u64) -> Self {
+        Self {
+            start,
+            next: Cell::new(start),
+        }
+    }
+}
+
+impl mmu::Allocator for Bump {
+    fn allocate(&self) -> Option<Phys> {
+        let frame = self.next.get();
+        if frame + PAGE_SIZE > self.start + Self::LEN {
+            return None;
+        }
+        self.next.set(frame + PAGE_SIZE);
+        Some(Phys::new(frame))
+    }
+
+    fn deallocate(&self, _: Phys) {}
 }
 
 #[panic_handler]
@@ -3,6 +3,7 @@ use core::time::Duration;
 use std::fs::File;
 use std::io::BufReader;
 use std::io::Read as _;
+use std::path::Path;
 use std::path::PathBuf;
 use std::time::Instant;
 
@@ -11,6 +12,7 @@ use serialport::ClearBuffer;
 use serialport::DataBits;
 use serialport::FlowControl;
 use serialport::Parity;
+use serialport::SerialPort;
 use serialport::StopBits;
 
 #[derive(clap::Parser)]
@@ -23,16 +25,15 @@ struct Cli {
 
     #[arg(short, long, default_value = "kernel8.img")]
     kernel: PathBuf,
+
+    /// cpio archive (newc format) to load as the initial ramdisk
+    #[arg(short, long)]
+    initrd: Option<PathBuf>,
 }
 
 fn main() {
     let cli = Cli::parse();
 
-    let file = File::open(&cli.kernel).unwrap();
-    let len = file.metadata().unwrap().len() as usize;
-    let eta = Duration::from_secs(len as u64 * 8 / (cli.baud as u64));
-    let mut file = BufReader::new(file);
-
     let mut port = serialport::new(&cli.port, cli.baud)
         .data_bits(DataBits::Eight)
         .stop_bits(StopBits::One)
@@ -57,14 +58,44 @@ fn main() {
         }
     }
 
+    send(&mut port, cli.baud, "kernel", Some(&cli.kernel));
+    send(&mut port, cli.baud, "initrd", cli.initrd.as_deref());
+
+    std::thread::scope(|scope| {
+        let mut tx = port.try_clone().unwrap();
+        let mut rx = port;
+        scope.spawn(move || {
+            let mut stdout = std::io::stdout().lock();
+            std::io::copy(&mut rx, &mut stdout).unwrap();
+        });
+
+        let mut stdin = std::io::stdin().lock();
+        std::io::copy(&mut stdin, &mut tx).unwrap();
+    });
+}
+
+/// Send the file at `path` prefixed by its little-endian `u64` length, or
+/// just a zero length if there is none.
+fn send(port: &mut Box<dyn SerialPort>, baud: u32, name: &str, path: Option<&Path>) {
+    let Some(path) = path else {
+        port.write_all(&0u64.to_le_bytes()).unwrap();
+        return;
+    };
+
+    let file = File::open(path).unwrap();
+    let len = file.metadata().unwrap().len() as usize;
+    let eta = Duration::from_secs(len as u64 * 8 / (baud as u64));
+    let mut file = BufReader::new(file);
+
     eprintln!(
-        "[PUSH] Sending {} at {} baud (~{})",
+        "[PUSH] Sending {} {} at {} baud (~{})",
+        name,
         Memory(len),
-        cli.baud,
+        baud,
         Time(eta),
     );
 
-    port.write_all(&len.to_le_bytes()).unwrap();
+    port.write_all(&(len as u64).to_le_bytes()).unwrap();
 
     const CHUNK: usize = 512;
 
@@ -82,24 +113,12 @@ fn main() {
             Time(eta),
             ((i * CHUNK * 100) as f64) / (len as f64),
         );
-        std::io::copy(&mut file.by_ref().take(CHUNK as u64), &mut port).unwrap();
+        std::io::copy(&mut file.by_ref().take(CHUNK as u64), port).unwrap();
 
         if i == chunks - 1 {
             eprintln!();
         }
     }
-
-    std::thread::scope(|scope| {
-        let mut tx = port.try_clone().unwrap();
-        let mut rx = port;
-        scope.spawn(move || {
-            let mut stdout = std::io::stdout().lock();
-            std::io::copy(&mut rx, &mut stdout).unwrap();
-        });
-
-        let mut stdin = std::io::stdin().lock();
-        std::io::copy(&mut stdin, &mut tx).unwrap();
-    });
 }
 
 struct Memory(usize);
@@ -7,6 +7,9 @@ use core::ptr::NonNull;
 
 use crate::Be32;
 use crate::Prop;
+use crate::Reg;
+use crate::RegIter;
+use crate::Reservation;
 use crate::StrIter;
 
 pub struct Blob<'dtb>(&'dtb [u8]);
@@ -30,7 +33,7 @@ impl<'dtb> Blob<'dtb> {
         unsafe { self.as_ptr().cast::<Header>().as_ref() }
     }
 
-    pub fn root(&self) -> Node {
+    pub fn root(&self) -> Root {
         let struct_offset = u32::from(self.header().off_dt_struct);
         let walk = unsafe {
             self.as_ptr()
@@ -38,9 +41,33 @@ impl<'dtb> Blob<'dtb> {
                 .cast::<Be32>()
         };
 
-        NodeIter(Cursor { dtb: self, walk })
-            .next()
-            .expect("Missing root node")
+        NodeIter {
+            cursor: Cursor { dtb: self, walk },
+            address_cells: 2,
+            size_cells: 1,
+        }
+        .next()
+        .map(Root)
+        .expect("Missing root node")
+    }
+
+    /// Iterate over the memory reservation block, which lists physical memory
+    /// that must not be used by the operating system.
+    pub fn reservations(&self) -> impl Iterator<Item = Reg> + 'dtb {
+        let offset = u32::from(self.header().off_mem_rsvmap);
+        let base = unsafe {
+            self.as_ptr()
+                .byte_add(offset as usize)
+                .cast::<Reservation>()
+        };
+
+        (0..)
+            .map(move |index| unsafe { base.add(index).read() })
+            .map(|reservation| Reg {
+                address: u64::from(reservation.address),
+                len: u64::from(reservation.size),
+            })
+            .take_while(|reg| reg.address != 0 || reg.len != 0)
     }
 
     fn str_offset(&self, offset: u32) -> &'dtb str {
@@ -95,22 +122,19 @@ impl<'dtb> Root<'dtb> {
         PropIter(cursor)
     }
 
-    pub fn memory(&self) -> Node<'dtb> {
-        let mut cursor = self.0.cursor.clone();
-        cursor.seek_child(|name| name == "memory");
-        NodeIter(cursor).next().expect("Missing /memory node")
+    /// Iterate over `/memory` and `/memory@<address>` nodes.
+    pub fn memory(&self) -> impl Iterator<Item = Node<'dtb>> + use<'dtb> {
+        self.0
+            .children()
+            .filter(|node| Node::matches(node.name, "memory"))
     }
 
     pub fn reserved_memory(&self) -> Option<Node<'dtb>> {
-        let mut cursor = self.0.cursor.clone();
-        cursor.seek_child(|name| name == "memory");
-        NodeIter(cursor).next()
+        self.child("reserved-memory")
     }
 
     pub fn cpus(&self) -> Node<'dtb> {
-        let mut cursor = self.0.cursor.clone();
-        cursor.seek_child(|name| name == "cpus");
-        NodeIter(cursor).next().expect("Missing /cpus node")
+        self.child("cpus").expect("Missing /cpus node")
     }
 }
 
@@ -121,6 +145,9 @@ pub struct Node<'dtb> {
     phandle: u32,
     address_cells: u32,
     size_cells: u32,
+    // Cells used to interpret this node's `reg` property, inherited from the parent
+    reg_address_cells: u32,
+    reg_size_cells: u32,
     // Positioned at first property within node
     cursor: Cursor<'dtb>,
 }
@@ -159,23 +186,65 @@ impl<'dtb> Node<'dtb> {
         PropIter(self.cursor.clone())
     }
 
+    pub fn prop(&self, name: &str) -> Option<Prop<'dtb>> {
+        self.props().find(|prop| prop.name() == name)
+    }
+
+    pub fn reg(&self) -> Option<RegIter<'dtb>> {
+        self.cursor.clone().find_prop("reg").map(|data| {
+            RegIter::new(
+                self.reg_address_cells as u64 * 4,
+                self.reg_size_cells as u64 * 4,
+                data,
+            )
+        })
+    }
+
     pub fn children(&self) -> NodeIter<'dtb> {
         let mut cursor = self.cursor.clone();
         cursor.seek_child(|_| true);
-        NodeIter(cursor)
+        NodeIter {
+            cursor,
+            address_cells: self.address_cells,
+            size_cells: self.size_cells,
+        }
+    }
+
+    /// Find a child by name, ignoring its unit address if `name` doesn't specify one.
+    pub fn child(&self, name: &str) -> Option<Node<'dtb>> {
+        let mut cursor = self.cursor.clone();
+        cursor.seek_child(|child| Self::matches(child, name));
+        NodeIter {
+            cursor,
+            address_cells: self.address_cells,
+            size_cells: self.size_cells,
+        }
+        .next()
+    }
+
+    fn matches(node: &str, name: &str) -> bool {
+        match node.strip_prefix(name) {
+            Some(unit) => unit.is_empty() || (unit.starts_with('@') && !name.contains('@')),
+            None => false,
+        }
     }
 }
 
 // Invariant: cursor always positioned at `Token::Begin` or `Token::End`
-pub struct NodeIter<'dtb>(Cursor<'dtb>);
+pub struct NodeIter<'dtb> {
+    cursor: Cursor<'dtb>,
+    // Cells of the parent node
+    address_cells: u32,
+    size_cells: u32,
+}
 
 impl<'dtb> Iterator for NodeIter<'dtb> {
     type Item = Node<'dtb>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let name = match self.0.peek()? {
+        let name = match self.cursor.peek()? {
             Token::Begin { name } => {
-                self.0.next();
+                self.cursor.next();
                 name
             }
             Token::Prop { .. } => unreachable!(),
@@ -183,28 +252,28 @@ impl<'dtb> Iterator for NodeIter<'dtb> {
         };
 
         let compatible = self
-            .0
+            .cursor
             .clone()
             .find_prop("compatible")
             .map(StrIter::new)
             .unwrap_or(StrIter::new(&[]));
 
         let phandle = self
-            .0
+            .cursor
             .clone()
             .find_prop("phandle")
             .map(|value| u32::from_be_bytes(value.try_into().expect("Invalid phandle prop")))
             .unwrap_or(u32::MAX);
 
         let address_cells = self
-            .0
+            .cursor
             .clone()
             .find_prop("#address-cells")
             .map(|value| u32::from_be_bytes(value.try_into().expect("Invalid #address-cells prop")))
             .unwrap_or(2);
 
         let size_cells = self
-            .0
+            .cursor
             .clone()
             .find_prop("#size-cells")
             .map(|value| u32::from_be_bytes(value.try_into().expect("Invalid #size-cells prop")))
@@ -216,10 +285,12 @@ impl<'dtb> Iterator for NodeIter<'dtb> {
             phandle,
             address_cells,
             size_cells,
-            cursor: self.0.clone(),
+            reg_address_cells: self.address_cells,
+            reg_size_cells: self.size_cells,
+            cursor: self.cursor.clone(),
         };
 
-        self.0.seek_sibling();
+        self.cursor.seek_sibling();
         Some(next)
     }
 }
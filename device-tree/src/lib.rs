@@ -11,6 +11,31 @@ pub struct Prop<'dtb> {
     value: &'dtb [u8],
 }
 
+impl<'dtb> Prop<'dtb> {
+    pub fn name(&self) -> &'dtb str {
+        self.name
+    }
+
+    pub fn value(&self) -> &'dtb [u8] {
+        self.value
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        self.value.try_into().ok().map(u32::from_be_bytes)
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self.value.len() {
+            4 => self.as_u32().map(u64::from),
+            _ => self.value.try_into().ok().map(u64::from_be_bytes),
+        }
+    }
+
+    pub fn as_strs(&self) -> StrIter<'dtb> {
+        StrIter::new(self.value)
+    }
+}
+
 #[repr(C, align(8))]
 #[derive(Copy, Clone, Debug)]
 pub struct Reservation {
@@ -34,15 +59,17 @@ impl<'dtb> RangeIter<'dtb> {
         }
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = Range> {
+    pub fn iter(&self) -> impl Iterator<Item = Range> + use<'dtb> {
+        let address_bytes = self.address_bytes;
+        let size_bytes = self.size_bytes;
         self.data
-            .chunks_exact((self.address_bytes * 2 + self.size_bytes) as usize)
+            .chunks_exact((address_bytes * 2 + size_bytes) as usize)
             .map(move |chunk| {
-                let (child, chunk) = chunk.split_at(self.address_bytes as usize);
-                let (parent, len) = chunk.split_at(self.address_bytes as usize);
-                let parent = variable_int(self.address_bytes, parent);
-                let child = variable_int(self.address_bytes, child);
-                let len = variable_int(self.size_bytes, len);
+                let (child, chunk) = chunk.split_at(address_bytes as usize);
+                let (parent, len) = chunk.split_at(address_bytes as usize);
+                let parent = variable_int(address_bytes, parent);
+                let child = variable_int(address_bytes, child);
+                let len = variable_int(size_bytes, len);
                 Range { child, parent, len }
             })
     }
@@ -87,13 +114,15 @@ impl<'dtb> RegIter<'dtb> {
         }
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = Reg> {
+    pub fn iter(&self) -> impl Iterator<Item = Reg> + use<'dtb> {
+        let address_bytes = self.address_bytes;
+        let size_bytes = self.size_bytes;
         self.data
-            .chunks_exact((self.address_bytes + self.size_bytes) as usize)
+            .chunks_exact((address_bytes + size_bytes) as usize)
             .map(move |chunk| {
-                let (address, len) = chunk.split_at(self.address_bytes as usize);
-                let address = variable_int(self.address_bytes, address);
-                let len = variable_int(self.size_bytes, len);
+                let (address, len) = chunk.split_at(address_bytes as usize);
+                let address = variable_int(address_bytes, address);
+                let len = variable_int(size_bytes, len);
                 Reg { address, len }
             })
     }
@@ -29,6 +29,7 @@
               (pkgs.rust-bin.fromRustupToolchainFile ./rust-toolchain.toml)
               pkgs.minicom
               pkgs.just
+              pkgs.cpio
             ];
 
             # Maybe the clearest explanation of the Nix cross-compilation model:
@@ -1,4 +1,5 @@
 use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
 
 #[repr(transparent)]
 pub struct Unsized([AtomicU64]);
@@ -61,6 +62,123 @@ macro_rules! impl_bit_set {
             Some(i * 64 + j as usize)
         }
 
+        pub fn count_ones(&self) -> usize {
+            self.0
+                .iter()
+                .map(|chunk| chunk.load(Ordering::Relaxed).count_ones() as usize)
+                .sum()
+        }
+
+        pub fn fill(&self) {
+            self.0
+                .iter()
+                .for_each(|chunk| chunk.store(u64::MAX, Ordering::Release));
+        }
+
+        pub fn clear(&self) {
+            self.0
+                .iter()
+                .for_each(|chunk| chunk.store(0, Ordering::Release));
+        }
+
+        pub fn get(&self, index: usize) -> bool {
+            let (i, j) = Self::split(index);
+            self.0[i].load(Ordering::Acquire) & (1 << j) > 0
+        }
+
+        /// Returns the previous value of the bit.
+        pub fn set(&self, index: usize) -> bool {
+            let (i, j) = Self::split(index);
+            self.0[i].fetch_or(1 << j, Ordering::AcqRel) & (1 << j) > 0
+        }
+
+        /// Returns the previous value of the bit.
+        pub fn unset(&self, index: usize) -> bool {
+            let (i, j) = Self::split(index);
+            self.0[i].fetch_and(!(1 << j), Ordering::AcqRel) & (1 << j) > 0
+        }
+
+        /// Atomically find and unset a set bit, scanning chunks starting at the
+        /// chunk containing `hint` and wrapping around.
+        pub fn unset_first(&self, hint: usize) -> Option<usize> {
+            let len = self.0.len();
+            let start = (hint / 64) % len.max(1);
+
+            (start..len).chain(0..start).find_map(|i| {
+                let chunk = &self.0[i];
+                let mut old = chunk.load(Ordering::Relaxed);
+                while old != 0 {
+                    let j = old.trailing_zeros();
+                    match chunk.compare_exchange_weak(
+                        old,
+                        old & !(1 << j),
+                        Ordering::AcqRel,
+                        Ordering::Relaxed,
+                    ) {
+                        Ok(_) => return Some(i * 64 + j as usize),
+                        Err(new) => old = new,
+                    }
+                }
+                None
+            })
+        }
+
+        /// Atomically unset every bit in `index..index + len` if and only if they are all set.
+        pub fn unset_range(&self, index: usize, len: usize) -> bool {
+            let mut chunks = Self::chunks(index, len);
+
+            for (k, (i, mask)) in chunks.clone().enumerate() {
+                let chunk = &self.0[i];
+                let mut old = chunk.load(Ordering::Relaxed);
+                loop {
+                    if old & mask != mask {
+                        // Roll back the chunks we already claimed
+                        chunks.by_ref().take(k).for_each(|(i, mask)| {
+                            self.0[i].fetch_or(mask, Ordering::Release);
+                        });
+                        return false;
+                    }
+
+                    match chunk.compare_exchange_weak(
+                        old,
+                        old & !mask,
+                        Ordering::AcqRel,
+                        Ordering::Relaxed,
+                    ) {
+                        Ok(_) => break,
+                        Err(new) => old = new,
+                    }
+                }
+            }
+
+            true
+        }
+
+        /// Set every bit in `index..index + len`, returning `true` if they were all unset.
+        pub fn set_range(&self, index: usize, len: usize) -> bool {
+            Self::chunks(index, len).fold(true, |unset, (i, mask)| {
+                self.0[i].fetch_or(mask, Ordering::AcqRel) & mask == 0 && unset
+            })
+        }
+
+        /// Returns `true` if every bit in `index..index + len` is set.
+        pub fn is_set_range(&self, index: usize, len: usize) -> bool {
+            Self::chunks(index, len)
+                .all(|(i, mask)| self.0[i].load(Ordering::Relaxed) & mask == mask)
+        }
+
+        fn chunks(index: usize, len: usize) -> impl Iterator<Item = (usize, u64)> + Clone {
+            (index / 64..(index + len).div_ceil(64)).map(move |i| {
+                let lo = (i * 64).max(index) - i * 64;
+                let hi = ((i + 1) * 64).min(index + len) - i * 64;
+                let mask = match hi - lo {
+                    64 => u64::MAX,
+                    bits => ((1 << bits) - 1) << lo,
+                };
+                (i, mask)
+            })
+        }
+
         const fn split(index: usize) -> (usize, u64) {
             (index / 64, (index % 64) as u64)
         }
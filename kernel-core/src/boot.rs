@@ -0,0 +1,42 @@
+/// Define the kernel's `_start` symbol, which enables the MMU with the page
+/// tables prepared by the bootloader, switches to the boot stack reserved by
+/// the linker script, and jumps to `_start_kernel` in the kernel address space.
+///
+/// Arguments from the bootloader in `x0` through `x4` are passed through, so
+/// only `x9` is used as scratch.
+#[macro_export]
+macro_rules! entry {
+    () => {
+        core::arch::global_asm! {
+        r"
+        .pushsection .text.boot
+
+        _start:
+            mrs x9, SCTLR_EL1
+
+            # M: MMU enable
+            orr x9, x9, (1 << 0)
+            # C: Cacheability for data accesses
+            orr x9, x9, (1 << 2)
+            # I: Cacheability for instruction accesses
+            orr x9, x9, (1 << 12)
+            # WXN: Writable memory is never executable
+            orr x9, x9, (1 << 19)
+
+            isb sy
+            msr SCTLR_EL1, x9
+            isb sy
+
+            ldr x9, =__STACK_HI
+            mov sp, x9
+            ldr x9, =_start_kernel
+            br x9
+
+        .size _start, . - _start
+        .type _start, %function
+        .global _start
+        .popsection
+        "
+        }
+    };
+}
@@ -0,0 +1,210 @@
+//! Reader for cpio archives in the "newc" (SVR4 without CRC) format, as
+//! produced by `find . | cpio -o -H newc`.
+//!
+//! Each entry is a 110-byte ASCII header, followed by the null-terminated
+//! path and then the file data, each padded to a multiple of four bytes.
+//! The archive ends with an entry named `TRAILER!!!`.
+
+use core::fmt::Debug;
+
+const MAGIC: &[u8] = b"070701";
+
+const HEADER: usize = 110;
+
+const TRAILER: &str = "TRAILER!!!";
+
+/// File type bits of [`Entry::mode`].
+pub mod mode {
+    pub const TYPE: u32 = 0o170000;
+    pub const DIRECTORY: u32 = 0o040000;
+    pub const FILE: u32 = 0o100000;
+    pub const SYMLINK: u32 = 0o120000;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Entry header or contents extend past the end of the archive
+    Truncated {
+        offset: usize,
+    },
+    BadMagic {
+        offset: usize,
+    },
+    /// Header field is not eight hexadecimal digits
+    BadHeader {
+        offset: usize,
+    },
+    /// Path is not null-terminated UTF-8
+    BadName {
+        offset: usize,
+    },
+}
+
+#[derive(Copy, Clone)]
+pub struct Archive<'a>(&'a [u8]);
+
+#[derive(Copy, Clone)]
+pub struct Entry<'a> {
+    path: &'a str,
+    mode: u32,
+    ino: u32,
+    data: &'a [u8],
+}
+
+impl<'a> Archive<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self(bytes)
+    }
+
+    /// Iterate over entries up to the trailer, stopping after the first error.
+    pub fn iter(&self) -> Iter<'a> {
+        Iter {
+            bytes: self.0,
+            offset: 0,
+            done: false,
+        }
+    }
+
+    /// Look up the entry at `path`, ignoring leading `/` and `./`.
+    pub fn get(&self, path: &str) -> Option<Entry<'a>> {
+        let path = normalize(path);
+        self.iter()
+            .map_while(Result::ok)
+            .find(|entry| entry.path() == path)
+    }
+}
+
+impl Debug for Archive<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a> Entry<'a> {
+    /// Path relative to the archive root, without a leading `/` or `./`.
+    pub fn path(&self) -> &'a str {
+        normalize(self.path)
+    }
+
+    /// File type and permission bits, as in `st_mode`.
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    pub fn ino(&self) -> u32 {
+        self.ino
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.mode & mode::TYPE == mode::DIRECTORY
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.mode & mode::TYPE == mode::FILE
+    }
+
+    /// File contents, or the target of a symbolic link.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+impl Debug for Entry<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Entry")
+            .field("path", &self.path())
+            .field("mode", &format_args!("{:#o}", self.mode))
+            .field("len", &self.data.len())
+            .finish()
+    }
+}
+
+pub struct Iter<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    done: bool,
+}
+
+impl<'a> Iter<'a> {
+    fn next_entry(&mut self) -> Result<Option<Entry<'a>>, Error> {
+        let offset = self.offset;
+        let header = self
+            .bytes
+            .get(offset..offset + HEADER)
+            .ok_or(Error::Truncated { offset })?;
+
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(Error::BadMagic { offset });
+        }
+
+        // Eight hexadecimal digits per field, following the magic
+        let field = |index: usize| {
+            let start = MAGIC.len() + index * 8;
+            core::str::from_utf8(&header[start..start + 8])
+                .ok()
+                .and_then(|field| u32::from_str_radix(field, 16).ok())
+                .ok_or(Error::BadHeader { offset })
+        };
+
+        let ino = field(0)?;
+        let mode = field(1)?;
+        let len = field(6)? as usize;
+        let name_len = field(11)? as usize;
+
+        let name_start = offset + HEADER;
+        let name = self
+            .bytes
+            .get(name_start..name_start + name_len)
+            .ok_or(Error::Truncated { offset })?;
+        let path = name
+            .split_last()
+            .filter(|(nul, _)| **nul == 0)
+            .and_then(|(_, name)| core::str::from_utf8(name).ok())
+            .ok_or(Error::BadName { offset })?;
+
+        let data_start = (name_start + name_len).next_multiple_of(4);
+        let data = self
+            .bytes
+            .get(data_start..data_start + len)
+            .ok_or(Error::Truncated { offset })?;
+
+        self.offset = (data_start + len).next_multiple_of(4);
+
+        if path == TRAILER {
+            return Ok(None);
+        }
+
+        Ok(Some(Entry {
+            path,
+            mode,
+            ino,
+            data,
+        }))
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Result<Entry<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let entry = self.next_entry();
+        self.done = !matches!(entry, Ok(Some(_)));
+        entry.transpose()
+    }
+}
+
+fn normalize(path: &str) -> &str {
+    let mut path = path;
+    while let Some(rest) = path.strip_prefix('/').or_else(|| path.strip_prefix("./")) {
+        path = rest;
+    }
+
+    match path {
+        "." => "",
+        path => path.trim_end_matches('/'),
+    }
+}
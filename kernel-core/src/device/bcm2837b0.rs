@@ -1,5 +1,23 @@
 pub mod clock;
 pub mod gpio;
 pub mod ic;
+pub mod mailbox;
 pub mod mini;
 pub mod uart;
+
+use core::ops::Range;
+
+/// Physical addresses of device MMIO, including the local interrupt controller.
+pub const MMIO: Range<u64> = 0x3F00_0000..0x4001_0000;
+
+/// Virtual address of device registers at physical `address`.
+///
+/// Code linked into the kernel address space accesses devices through the
+/// linear map, so the identity map can be removed; code linked at physical
+/// addresses (e.g. the bootloader) accesses them directly.
+pub fn mmio(address: usize) -> usize {
+    match mmio as usize as u64 >= crate::mem::OFFSET {
+        true => address + crate::mem::OFFSET as usize,
+        false => address,
+    }
+}
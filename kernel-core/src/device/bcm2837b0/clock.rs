@@ -9,6 +9,8 @@ use tock_registers::registers::ReadOnly;
 use tock_registers::registers::ReadWrite;
 use tock_registers::registers::WriteOnly;
 
+use crate::device::bcm2837b0::mmio;
+
 pub struct Clock {
     address: usize,
 }
@@ -33,15 +35,17 @@ impl Clock {
 impl Deref for Clock {
     type Target = Mmio;
     fn deref(&self) -> &Self::Target {
-        unsafe { core::ptr::with_exposed_provenance::<Self::Target>(self.address).as_ref() }
+        unsafe { core::ptr::with_exposed_provenance::<Self::Target>(mmio(self.address)).as_ref() }
             .unwrap()
     }
 }
 
 impl DerefMut for Clock {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { core::ptr::with_exposed_provenance_mut::<Self::Target>(self.address).as_mut() }
-            .unwrap()
+        unsafe {
+            core::ptr::with_exposed_provenance_mut::<Self::Target>(mmio(self.address)).as_mut()
+        }
+        .unwrap()
     }
 }
 
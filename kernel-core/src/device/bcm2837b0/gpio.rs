@@ -1,41 +1,93 @@
 use core::ops::Deref;
 use core::ops::DerefMut;
-use core::sync::atomic::AtomicU64;
-use core::sync::atomic::Ordering;
 
 use aarch64_cpu::registers::Readable as _;
 use aarch64_cpu::registers::Writeable as _;
 use tock_registers::register_bitfields;
 use tock_registers::register_structs;
+use tock_registers::registers::ReadOnly;
 use tock_registers::registers::ReadWrite;
+use tock_registers::registers::WriteOnly;
 
-use crate::time;
-use crate::time::Cycle;
+use crate::device::bcm2837b0::mmio;
 
 pub struct Gpio {
     address: usize,
 }
 
 impl Gpio {
+    pub const PINS: usize = 54;
+
     pub const unsafe fn new(address: usize) -> Self {
         Self { address }
     }
 
+    /// Route GPIO14/15 to the mini UART (TXD1/RXD1).
     pub fn init(&self) {
-        let mut selector = self.function_select[1].get();
+        self.set_function(14, Function::Alt5);
+        self.set_function(15, Function::Alt5);
+        self.set_pull_mask(0, (1 << 14) | (1 << 15), Pull::Off);
+    }
+
+    /// Route GPIO14/15 to the PL011 UART (TXD0/RXD0), and optionally
+    /// GPIO16/17 to its hardware flow control lines (CTS0/RTS0).
+    pub fn init_pl011(&self, rts_cts: bool) {
+        let mut pins = (1 << 14) | (1 << 15);
 
-        selector &= !(0b111 << 12);
-        selector |= (Select::Alt5 as u32) << 12;
+        self.set_function(14, Function::Alt0);
+        self.set_function(15, Function::Alt0);
 
-        selector &= !(0b111 << 15);
-        selector |= (Select::Alt5 as u32) << 15;
+        if rts_cts {
+            self.set_function(16, Function::Alt3);
+            self.set_function(17, Function::Alt3);
+            pins |= (1 << 16) | (1 << 17);
+        }
 
-        self.function_select[1].set(selector);
+        self.set_pull_mask(0, pins, Pull::Off);
+    }
 
-        self.pull_enable.write(PullEnable::PUD::Off);
-        // let spin = Cycle::new(150);
-        // time::spin_cycle(spin);
+    pub fn set_function(&self, pin: usize, function: Function) {
+        let (register, shift) = Self::split_function(pin);
+        let register = &self.function_select[register];
+
+        let mut selector = register.get();
+        selector &= !(0b111 << shift);
+        selector |= (function as u32) << shift;
+        register.set(selector);
+    }
+
+    pub fn function(&self, pin: usize) -> Function {
+        let (register, shift) = Self::split_function(pin);
+        Function::from_bits((self.function_select[register].get() >> shift) & 0b111)
+    }
+
+    /// Drive an output pin high.
+    pub fn set(&self, pin: usize) {
+        let (bank, bit) = Self::split(pin);
+        self.set[bank].set(bit);
+    }
 
+    /// Drive an output pin low.
+    pub fn clear(&self, pin: usize) {
+        let (bank, bit) = Self::split(pin);
+        self.clear[bank].set(bit);
+    }
+
+    pub fn read(&self, pin: usize) -> bool {
+        let (bank, bit) = Self::split(pin);
+        self.level[bank].get() & bit > 0
+    }
+
+    pub fn set_pull(&self, pin: usize, pull: Pull) {
+        let (bank, bit) = Self::split(pin);
+        self.set_pull_mask(bank, bit, pull);
+    }
+
+    /// Configure the pull-up/down resistors of every pin in `bank` selected by `pins`.
+    ///
+    /// The BCM2837 requires the control signal to be held for 150 cycles before
+    /// and after clocking it into the selected pins.
+    pub fn set_pull_mask(&self, bank: usize, pins: u32, pull: Pull) {
         macro_rules! delay {
             ($cycles:expr) => {
                 unsafe {
@@ -49,27 +101,83 @@ impl Gpio {
             };
         }
 
-        // let spin = AtomicU64::new(0);
-        // for _ in 0..150 {
-        //     let old = spin.load(Ordering::Relaxed);
-        //     spin.store(old + 1, Ordering::Relaxed);
-        // }
-        //
+        self.pull_enable.write(match pull {
+            Pull::Off => PullEnable::PUD::Off,
+            Pull::Down => PullEnable::PUD::PullDown,
+            Pull::Up => PullEnable::PUD::PullUp,
+        });
         delay!(150);
 
-        self.pull_clock[0].set((1 << 14) | (1 << 15));
-        // for _ in 0..150 {
-        //     let old = spin.load(Ordering::Relaxed);
-        //     spin.store(old + 1, Ordering::Relaxed);
-        // }
-        // time::spin_cycle(spin);
-
+        self.pull_clock[bank].set(pins);
         delay!(150);
-        self.pull_clock[0].set(0);
+
+        self.pull_enable.write(PullEnable::PUD::Off);
+        self.pull_clock[bank].set(0);
+    }
+
+    /// Start latching `detect` events for `pin` into the event detect status register,
+    /// which also raises the corresponding GPIO interrupt if it is enabled in the
+    /// interrupt controller.
+    pub fn enable_detect(&self, pin: usize, detect: Detect) {
+        let (bank, bit) = Self::split(pin);
+        let register = &self.detect(detect)[bank];
+        register.set(register.get() | bit);
+    }
+
+    pub fn disable_detect(&self, pin: usize, detect: Detect) {
+        let (bank, bit) = Self::split(pin);
+        let register = &self.detect(detect)[bank];
+        register.set(register.get() & !bit);
+    }
+
+    pub fn is_event(&self, pin: usize) -> bool {
+        let (bank, bit) = Self::split(pin);
+        self.event[bank].get() & bit > 0
+    }
+
+    /// Acknowledge a latched event, which deasserts the GPIO interrupt once
+    /// every pending event in the bank is cleared.
+    pub fn clear_event(&self, pin: usize) {
+        let (bank, bit) = Self::split(pin);
+        self.event[bank].set(bit);
+    }
+
+    /// Iterate over and acknowledge all pins with latched events.
+    pub fn drain_events(&self) -> impl Iterator<Item = usize> {
+        (0..2).flat_map(move |bank| {
+            let pending = self.event[bank].get();
+            self.event[bank].set(pending);
+            (0..32)
+                .filter(move |bit| pending & (1 << bit) > 0)
+                .map(move |bit| bank * 32 + bit)
+                .filter(|pin| *pin < Self::PINS)
+        })
+    }
+
+    fn detect(&self, detect: Detect) -> &[ReadWrite<u32>; 2] {
+        match detect {
+            Detect::Rising => &self.rising_edge,
+            Detect::Falling => &self.falling_edge,
+            Detect::High => &self.high_level,
+            Detect::Low => &self.low_level,
+            Detect::AsyncRising => &self.async_rising_edge,
+            Detect::AsyncFalling => &self.async_falling_edge,
+        }
+    }
+
+    const fn split(pin: usize) -> (usize, u32) {
+        assert!(pin < Self::PINS);
+        (pin / 32, 1 << (pin % 32))
+    }
+
+    const fn split_function(pin: usize) -> (usize, usize) {
+        assert!(pin < Self::PINS);
+        (pin / 10, (pin % 10) * 3)
     }
 }
 
-enum Select {
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Function {
     Input = 0b000,
     Output = 0b001,
     Alt0 = 0b100,
@@ -80,12 +188,49 @@ enum Select {
     Alt5 = 0b010,
 }
 
+impl Function {
+    const fn from_bits(bits: u32) -> Self {
+        match bits {
+            0b000 => Function::Input,
+            0b001 => Function::Output,
+            0b100 => Function::Alt0,
+            0b101 => Function::Alt1,
+            0b110 => Function::Alt2,
+            0b111 => Function::Alt3,
+            0b011 => Function::Alt4,
+            0b010 => Function::Alt5,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pull {
+    Off,
+    Down,
+    Up,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Detect {
+    /// Synchronous rising edge (sampled with the system clock)
+    Rising,
+    /// Synchronous falling edge (sampled with the system clock)
+    Falling,
+    High,
+    Low,
+    /// Asynchronous rising edge, for detecting very short pulses
+    AsyncRising,
+    /// Asynchronous falling edge, for detecting very short pulses
+    AsyncFalling,
+}
+
 impl Deref for Gpio {
     type Target = Mmio;
 
     #[inline]
     fn deref(&self) -> &Self::Target {
-        unsafe { core::ptr::with_exposed_provenance::<Self::Target>(self.address).as_ref() }
+        unsafe { core::ptr::with_exposed_provenance::<Self::Target>(mmio(self.address)).as_ref() }
             .unwrap()
     }
 }
@@ -93,8 +238,10 @@ impl Deref for Gpio {
 impl DerefMut for Gpio {
     #[inline]
     fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { core::ptr::with_exposed_provenance_mut::<Self::Target>(self.address).as_mut() }
-            .unwrap()
+        unsafe {
+            core::ptr::with_exposed_provenance_mut::<Self::Target>(mmio(self.address)).as_mut()
+        }
+        .unwrap()
     }
 }
 
@@ -102,10 +249,30 @@ register_structs! {
     #[allow(non_snake_case)]
     pub Mmio {
         (0x00 => function_select: [ReadWrite<u32>; 6]),
-        (0x18 => _reserved2),
+        (0x18 => _reserved0),
+        (0x1C => set: [WriteOnly<u32>; 2]),
+        (0x24 => _reserved1),
+        (0x28 => clear: [WriteOnly<u32>; 2]),
+        (0x30 => _reserved2),
+        (0x34 => level: [ReadOnly<u32>; 2]),
+        (0x3C => _reserved3),
+        (0x40 => event: [ReadWrite<u32>; 2]),
+        (0x48 => _reserved4),
+        (0x4C => rising_edge: [ReadWrite<u32>; 2]),
+        (0x54 => _reserved5),
+        (0x58 => falling_edge: [ReadWrite<u32>; 2]),
+        (0x60 => _reserved6),
+        (0x64 => high_level: [ReadWrite<u32>; 2]),
+        (0x6C => _reserved7),
+        (0x70 => low_level: [ReadWrite<u32>; 2]),
+        (0x78 => _reserved8),
+        (0x7C => async_rising_edge: [ReadWrite<u32>; 2]),
+        (0x84 => _reserved9),
+        (0x88 => async_falling_edge: [ReadWrite<u32>; 2]),
+        (0x90 => _reserved10),
         (0x94 => pull_enable: ReadWrite<u32, PullEnable::Register>),
         (0x98 => pull_clock: [ReadWrite<u32>; 2]),
-        (0xA0 => _reserved3),
+        (0xA0 => _reserved11),
         (0xE8 => @END),
     }
 }
@@ -1,16 +1,20 @@
 pub use core::Core;
+pub use core::Source;
 pub use peripheral::Peripheral;
 
 mod peripheral {
     use core::ops::Deref;
     use core::ops::DerefMut;
 
+    use aarch64_cpu::registers::Readable as _;
     use aarch64_cpu::registers::Writeable as _;
     use tock_registers::register_bitfields;
     use tock_registers::register_structs;
     use tock_registers::registers::ReadOnly;
     use tock_registers::registers::ReadWrite;
 
+    use crate::device::bcm2837b0::mmio;
+
     pub struct Peripheral {
         address: usize,
     }
@@ -20,23 +24,51 @@ mod peripheral {
             Self { address }
         }
 
+        /// GPU interrupt lines raised by GPIO event detection, one per bank
+        /// followed by a line shared by all banks.
+        pub const IRQ_GPIO: [usize; 4] = [49, 50, 51, 52];
+
         pub fn init(&self) {
             self.enable_basic.write(Basic::TIMER::SET);
         }
+
+        pub fn enable_irq(&self, irq: usize) {
+            let (bank, bit) = Self::split(irq);
+            self.enable[bank].set(bit);
+        }
+
+        pub fn disable_irq(&self, irq: usize) {
+            let (bank, bit) = Self::split(irq);
+            self.disable[bank].set(bit);
+        }
+
+        pub fn is_pending(&self, irq: usize) -> bool {
+            let (bank, bit) = Self::split(irq);
+            self.pending[bank].get() & bit > 0
+        }
+
+        const fn split(irq: usize) -> (usize, u32) {
+            assert!(irq < 64);
+            (irq / 32, 1 << (irq % 32))
+        }
     }
 
     impl Deref for Peripheral {
         type Target = Mmio;
         fn deref(&self) -> &Self::Target {
-            unsafe { core::ptr::with_exposed_provenance::<Self::Target>(self.address).as_ref() }
-                .unwrap()
+            unsafe {
+                core::ptr::with_exposed_provenance::<Self::Target>(mmio(self.address)).as_ref()
+            }
+            .unwrap()
         }
     }
 
     impl DerefMut for Peripheral {
         fn deref_mut(&mut self) -> &mut Self::Target {
-            unsafe { core::ptr::with_exposed_provenance_mut::<Self::Target>(self.address).as_mut() }
-                .unwrap()
+            unsafe {
+                core::ptr::with_exposed_provenance_mut::<Self::Target>(mmio(self.address)).as_mut()
+            }
+            .unwrap()
         }
     }
 
@@ -79,12 +111,17 @@ mod core {
     use core::ops::Deref;
     use core::ops::DerefMut;
 
+    use aarch64_cpu::registers::Readable as _;
     use aarch64_cpu::registers::Writeable as _;
+    use tock_registers::LocalRegisterCopy;
     use tock_registers::register_bitfields;
     use tock_registers::register_structs;
     use tock_registers::registers::ReadOnly;
+    use tock_registers::registers::ReadWrite;
     use tock_registers::registers::WriteOnly;
 
+    use crate::device::bcm2837b0::mmio;
+
     pub struct Core {
         address: usize,
     }
@@ -94,23 +131,47 @@ mod core {
             Self { address }
         }
 
-        pub fn init(&self) {
-            self.timer[0].write(Timer::PNS_IRQ::SET);
+        /// Route the non-secure physical timer interrupt and mailbox 0 of
+        /// `cpu` to its IRQ.
+        pub fn init(&self, cpu: usize) {
+            self.timer[cpu].write(Timer::PNS_IRQ::SET);
+            self.mailbox[cpu].write(Mailbox::M0_IRQ::SET);
+        }
+
+        /// Pending interrupt sources of `cpu`.
+        pub fn source(&self, cpu: usize) -> LocalRegisterCopy<u32, Source::Register> {
+            self.source_irq[cpu].extract()
+        }
+
+        /// Set `bits` in `mailbox` of `cpu`, raising its interrupt if enabled.
+        pub fn send(&self, cpu: usize, mailbox: usize, bits: u32) {
+            self.mailbox_set[cpu * 4 + mailbox].set(bits);
+        }
+
+        /// Read and clear the bits set in `mailbox` of `cpu`.
+        pub fn take(&self, cpu: usize, mailbox: usize) -> u32 {
+            let bits = self.mailbox_clear[cpu * 4 + mailbox].get();
+            self.mailbox_clear[cpu * 4 + mailbox].set(bits);
+            bits
         }
     }
 
     impl Deref for Core {
         type Target = Mmio;
         fn deref(&self) -> &Self::Target {
-            unsafe { core::ptr::with_exposed_provenance::<Self::Target>(self.address).as_ref() }
-                .unwrap()
+            unsafe {
+                core::ptr::with_exposed_provenance::<Self::Target>(mmio(self.address)).as_ref()
+            }
+            .unwrap()
         }
     }
 
     impl DerefMut for Core {
         fn deref_mut(&mut self) -> &mut Self::Target {
-            unsafe { core::ptr::with_exposed_provenance_mut::<Self::Target>(self.address).as_mut() }
-                .unwrap()
+            unsafe {
+                core::ptr::with_exposed_provenance_mut::<Self::Target>(mmio(self.address)).as_mut()
+            }
+            .unwrap()
         }
     }
 
@@ -121,7 +182,11 @@ mod core {
             (0x50 => mailbox: [WriteOnly<u32, Mailbox::Register>; 4]),
             (0x60 => source_irq: [ReadOnly<u32, Source::Register>; 4]),
             (0x70 => source_fiq: [ReadOnly<u32, Source::Register>; 4]),
-            (0x80 => @END),
+            // Write-set, indexed by core * 4 + mailbox
+            (0x80 => mailbox_set: [WriteOnly<u32>; 16]),
+            // Read, and write-clear
+            (0xC0 => mailbox_clear: [ReadWrite<u32>; 16]),
+            (0x100 => @END),
         }
     }
 
@@ -0,0 +1,184 @@
+use core::ops::Deref;
+use core::ops::DerefMut;
+use core::ops::Range;
+
+use aarch64_cpu::asm::barrier;
+use aarch64_cpu::registers::Readable as _;
+use aarch64_cpu::registers::Writeable as _;
+use tock_registers::register_bitfields;
+use tock_registers::register_structs;
+use tock_registers::registers::ReadOnly;
+use tock_registers::registers::WriteOnly;
+
+use crate::device::bcm2837b0::mmio;
+use crate::mem::Kernel;
+use crate::mem::Virt;
+
+// https://github.com/raspberrypi/firmware/wiki/Mailbox-property-interface
+pub struct Mailbox {
+    address: usize,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Clock {
+    Emmc = 1,
+    Uart = 2,
+    Arm = 3,
+    Core = 4,
+}
+
+impl Mailbox {
+    const CHANNEL_PROPERTY: u32 = 8;
+
+    const REQUEST: u32 = 0;
+    const RESPONSE_SUCCESS: u32 = 0x8000_0000;
+
+    const TAG_GET_ARM_MEMORY: u32 = 0x0001_0005;
+    const TAG_GET_VC_MEMORY: u32 = 0x0001_0006;
+    const TAG_GET_CLOCK_RATE: u32 = 0x0003_0002;
+    const TAG_END: u32 = 0;
+
+    /// # Safety
+    ///
+    /// Caller must guarantee that `address` maps the VideoCore mailbox
+    /// registers, and that no other instance uses them concurrently.
+    pub const unsafe fn new(address: usize) -> Self {
+        Self { address }
+    }
+
+    pub fn clock_rate(&mut self, clock: Clock) -> Option<u32> {
+        let [_, rate] = self.property(Self::TAG_GET_CLOCK_RATE, [clock as u32, 0])?;
+        Some(rate)
+    }
+
+    /// Physical memory assigned to the ARM cores by the firmware's memory split.
+    pub fn arm_memory(&mut self) -> Option<Range<u64>> {
+        let [base, len] = self.property(Self::TAG_GET_ARM_MEMORY, [0; 2])?;
+        Some(base as u64..base as u64 + len as u64)
+    }
+
+    /// Physical memory reserved for the VideoCore GPU.
+    pub fn vc_memory(&mut self) -> Option<Range<u64>> {
+        let [base, len] = self.property(Self::TAG_GET_VC_MEMORY, [0; 2])?;
+        Some(base as u64..base as u64 + len as u64)
+    }
+
+    /// Send a single tag with a two word value buffer.
+    fn property(&mut self, tag: u32, value: [u32; 2]) -> Option<[u32; 2]> {
+        let mut message = Message([
+            0,
+            Self::REQUEST,
+            tag,
+            8,
+            0,
+            value[0],
+            value[1],
+            Self::TAG_END,
+        ]);
+
+        self.call(&mut message)?;
+        Some([message.0[5], message.0[6]])
+    }
+
+    /// Send a property message to the VideoCore firmware and wait for its response,
+    /// which is written back into `message`.
+    pub fn call<const N: usize>(&mut self, message: &mut Message<N>) -> Option<()> {
+        message.0[0] = core::mem::size_of::<Message<N>>() as u32;
+
+        let address = message as *mut Message<N> as u64;
+
+        // The firmware addresses memory physically and does not snoop the CPU caches.
+        // Thread stacks lie outside the linear map, so kernel addresses are
+        // translated by the hardware walker.
+        let phys = match address >= crate::mem::OFFSET {
+            true => crate::mmu::translate(Virt::<Kernel>::new(address))
+                .map(u64::from)
+                .expect("Mailbox message not mapped"),
+            false => address,
+        };
+
+        assert!(phys & 0xF == 0);
+        assert!(phys < (1 << 32));
+
+        Self::clean(message);
+
+        while self.status.is_set(Status::FULL) {
+            crate::pause();
+        }
+
+        self.write.set(phys as u32 | Self::CHANNEL_PROPERTY);
+
+        loop {
+            while self.status.is_set(Status::EMPTY) {
+                crate::pause();
+            }
+
+            if self.read.get() & 0xF == Self::CHANNEL_PROPERTY {
+                break;
+            }
+        }
+
+        Self::clean(message);
+
+        match message.0[1] {
+            Self::RESPONSE_SUCCESS => Some(()),
+            _ => None,
+        }
+    }
+
+    fn clean<const N: usize>(message: &Message<N>) {
+        let start = message as *const Message<N> as u64;
+        let end = start + core::mem::size_of::<Message<N>>() as u64;
+
+        for line in (start & !63..end).step_by(64) {
+            unsafe {
+                core::arch::asm!("dc civac, {line}", line = in(reg) line, options(nostack));
+            }
+        }
+
+        barrier::dsb(barrier::SY);
+    }
+}
+
+/// Property interface buffer, which must be 16-byte aligned because the
+/// lower four bits of its address are used to encode the channel.
+#[repr(C, align(16))]
+pub struct Message<const N: usize>(pub [u32; N]);
+
+impl Deref for Mailbox {
+    type Target = Mmio;
+    fn deref(&self) -> &Self::Target {
+        unsafe { core::ptr::with_exposed_provenance::<Self::Target>(mmio(self.address)).as_ref() }
+            .unwrap()
+    }
+}
+
+impl DerefMut for Mailbox {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe {
+            core::ptr::with_exposed_provenance_mut::<Self::Target>(mmio(self.address)).as_mut()
+        }
+        .unwrap()
+    }
+}
+
+register_structs! {
+    pub Mmio {
+        (0x00 => read: ReadOnly<u32>),
+        (0x04 => _reserved0),
+        (0x18 => status: ReadOnly<u32, Status::Register>),
+        (0x1c => _reserved1),
+        (0x20 => write: WriteOnly<u32>),
+        (0x24 => @END),
+    }
+}
+
+register_bitfields! {
+    u32,
+
+    Status [
+        EMPTY OFFSET(30) NUMBITS(1) [],
+        FULL OFFSET(31) NUMBITS(1) [],
+    ],
+}
@@ -9,11 +9,16 @@ use tock_registers::register_structs;
 use tock_registers::registers::ReadOnly;
 use tock_registers::registers::ReadWrite;
 
+use crate::device::bcm2837b0::mmio;
+
 pub struct Uart {
     address: usize,
 }
 
 impl Uart {
+    /// GPU interrupt line shared by the auxiliary peripherals.
+    pub const IRQ: usize = 29;
+
     pub const unsafe fn new(address: usize) -> Self {
         Self { address }
     }
@@ -34,6 +39,17 @@ impl Uart {
             .write(Control::RX::Enable + Control::TX::Enable);
     }
 
+    /// Raise [`Uart::IRQ`] while received data is available.
+    pub fn enable_rx_interrupt(&self) {
+        self.interrupt_control.write(InterruptControl::RX::Enable);
+    }
+
+    pub fn try_read_byte(&mut self) -> Option<u8> {
+        self.line_status
+            .is_set(LineStatus::RX_READY)
+            .then(|| self.io.get() as u8)
+    }
+
     pub fn read_byte(&mut self) -> u8 {
         while !self.line_status.is_set(LineStatus::RX_READY) {
             crate::pause();
@@ -70,15 +86,17 @@ impl Write for Uart {
 impl Deref for Uart {
     type Target = Mmio;
     fn deref(&self) -> &Self::Target {
-        unsafe { core::ptr::with_exposed_provenance::<Self::Target>(self.address).as_ref() }
+        unsafe { core::ptr::with_exposed_provenance::<Self::Target>(mmio(self.address)).as_ref() }
             .unwrap()
     }
 }
 
 impl DerefMut for Uart {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { core::ptr::with_exposed_provenance_mut::<Self::Target>(self.address).as_mut() }
-            .unwrap()
+        unsafe {
+            core::ptr::with_exposed_provenance_mut::<Self::Target>(mmio(self.address)).as_mut()
+        }
+        .unwrap()
     }
 }
 
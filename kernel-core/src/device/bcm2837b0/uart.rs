@@ -2,6 +2,7 @@ use core::fmt::Write;
 use core::ops::Deref;
 use core::ops::DerefMut;
 
+use aarch64_cpu::registers::ReadWriteable as _;
 use aarch64_cpu::registers::Readable as _;
 use aarch64_cpu::registers::Writeable as _;
 use tock_registers::register_bitfields;
@@ -10,30 +11,125 @@ use tock_registers::registers::ReadOnly;
 use tock_registers::registers::ReadWrite;
 use tock_registers::registers::WriteOnly;
 
+use crate::device::bcm2837b0::mmio;
+
 pub struct Uart {
     address: usize,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub baud: u32,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+    pub data_bits: DataBits,
+    pub rts_cts: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            baud: 115_200,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            data_bits: DataBits::Eight,
+            rts_cts: false,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DataBits {
+    Five,
+    Six,
+    Seven,
+    Eight,
+}
+
 impl Uart {
+    /// UART reference clock configured by the firmware when `init_uart_clock` is unset.
+    pub const DEFAULT_CLOCK: u32 = 48_000_000;
+
     pub const unsafe fn new(address: usize) -> Self {
         Self { address }
     }
 
-    pub fn initialize(&mut self) {
+    /// Configure the UART given the frequency of its reference clock in Hz.
+    ///
+    /// Note: the caller is responsible for muxing the TX/RX (and CTS/RTS) pins.
+    pub fn initialize(&mut self, config: &Config, clock: u32) {
+        let (integer, fractional) = Self::divisor(config.baud, clock);
+
         self.flush();
         self.control
             .write(Control::UARTEN::Disabled + Control::TXE::Disabled + Control::RXE::Disabled);
         self.interrupt_clear.write(InterruptClear::ALL::CLEAR);
 
         self.integer_baud_rate
-            .write(IntegerBaudRate::BAUD_DIVINT.val(3));
+            .write(IntegerBaudRate::BAUD_DIVINT.val(integer));
         self.fractional_baud_rate
-            .write(FractionalBaudRate::BAUD_DIVFRAC.val(16));
-        self.line_control
-            .write(LineControl::WLEN::EightBit + LineControl::FEN::FifosEnabled);
+            .write(FractionalBaudRate::BAUD_DIVFRAC.val(fractional));
 
-        self.control
-            .write(Control::UARTEN::Enabled + Control::TXE::Enabled + Control::RXE::Enabled);
+        let mut line_control = LineControl::FEN::FifosEnabled
+            + match config.data_bits {
+                DataBits::Five => LineControl::WLEN::FiveBit,
+                DataBits::Six => LineControl::WLEN::SixBit,
+                DataBits::Seven => LineControl::WLEN::SevenBit,
+                DataBits::Eight => LineControl::WLEN::EightBit,
+            }
+            + match config.stop_bits {
+                StopBits::One => LineControl::STP2::One,
+                StopBits::Two => LineControl::STP2::Two,
+            };
+
+        line_control += match config.parity {
+            Parity::None => LineControl::PEN::Disabled,
+            Parity::Odd => LineControl::PEN::Enabled + LineControl::EPS::Odd,
+            Parity::Even => LineControl::PEN::Enabled + LineControl::EPS::Even,
+        };
+
+        // Line control register must be written after the baud rate divisors
+        self.line_control.write(line_control);
+
+        let flow_control = match config.rts_cts {
+            true => Control::CTSEN::Enabled + Control::RTSEN::Enabled,
+            false => Control::CTSEN::Disabled + Control::RTSEN::Disabled,
+        };
+
+        self.control.write(
+            Control::UARTEN::Enabled + Control::TXE::Enabled + Control::RXE::Enabled + flow_control,
+        );
+    }
+
+    /// Compute the integer and fractional baud rate divisors, where
+    /// `BAUDDIV = clock / (16 * baud)` and the fractional part is in 1/64ths.
+    fn divisor(baud: u32, clock: u32) -> (u32, u32) {
+        assert!(baud > 0);
+
+        // Round to nearest 1/64th
+        let divisor = ((clock as u64) * 4 + (baud as u64) / 2) / (baud as u64);
+        let integer = (divisor >> 6) as u32;
+        let fractional = (divisor & 0x3F) as u32;
+
+        assert!(
+            (1..=0xFFFF).contains(&integer),
+            "Unsupported baud rate {baud} for UART clock {clock}",
+        );
+
+        (integer, fractional)
     }
 
     pub fn read(&mut self, buffer: &mut [u8]) -> crate::Result<usize> {
@@ -45,6 +141,20 @@ impl Uart {
         Ok(1)
     }
 
+    pub fn try_read_byte(&mut self) -> Option<u8> {
+        (!self.flag.is_set(Flag::RXFE)).then(|| self.data.get() as u8)
+    }
+
+    /// Route transmitted data straight back to the receiver instead of the
+    /// TX pin, e.g. to check that output reaches this UART.
+    pub fn set_loopback(&mut self, enable: bool) {
+        self.flush();
+        self.control.modify(match enable {
+            true => Control::LBE::Enabled,
+            false => Control::LBE::Disabled,
+        });
+    }
+
     pub fn write_byte(&mut self, byte: u8) {
         while self.flag.is_set(Flag::TXFF) {
             crate::pause();
@@ -73,15 +183,17 @@ impl Write for Uart {
 impl Deref for Uart {
     type Target = Mmio;
     fn deref(&self) -> &Self::Target {
-        unsafe { core::ptr::with_exposed_provenance::<Self::Target>(self.address).as_ref() }
+        unsafe { core::ptr::with_exposed_provenance::<Self::Target>(mmio(self.address)).as_ref() }
             .unwrap()
     }
 }
 
 impl DerefMut for Uart {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { core::ptr::with_exposed_provenance_mut::<Self::Target>(self.address).as_mut() }
-            .unwrap()
+        unsafe {
+            core::ptr::with_exposed_provenance_mut::<Self::Target>(mmio(self.address)).as_mut()
+        }
+        .unwrap()
     }
 }
 
@@ -94,7 +206,7 @@ register_structs! {
         (0x24 => integer_baud_rate: WriteOnly<u32, IntegerBaudRate::Register>),
         (0x28 => fractional_baud_rate: WriteOnly<u32, FractionalBaudRate::Register>),
         (0x2c => line_control: WriteOnly<u32, LineControl::Register>),
-        (0x30 => control: WriteOnly<u32, Control::Register>),
+        (0x30 => control: ReadWrite<u32, Control::Register>),
         (0x34 => _reserved3),
         (0x44 => interrupt_clear: WriteOnly<u32, InterruptClear::Register>),
         (0x48 => @END),
@@ -171,11 +283,47 @@ register_bitfields! {
         FEN  OFFSET(4) NUMBITS(1) [
             FifosDisabled = 0,
             FifosEnabled = 1
+        ],
+
+        /// Two stop bits select. If this bit is set to 1, two stop bits are transmitted at the end
+        /// of the frame. The receive logic does not check for two stop bits being received.
+        STP2 OFFSET(3) NUMBITS(1) [
+            One = 0,
+            Two = 1
+        ],
+
+        /// Even parity select. Controls the type of parity the UART uses during transmission and
+        /// reception. This bit has no effect when the PEN bit disables parity checking and
+        /// generation.
+        EPS OFFSET(2) NUMBITS(1) [
+            Odd = 0,
+            Even = 1
+        ],
+
+        /// Parity enable. If this bit is set to 1, parity checking and generation is enabled.
+        PEN OFFSET(1) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
         ]
     ],
 
     /// Control Register.
     Control [
+        /// CTS hardware flow control enable. If this bit is set to 1, CTS hardware flow control is
+        /// enabled. Data is only transmitted when the nUARTCTS signal is asserted.
+        CTSEN OFFSET(15) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ],
+
+        /// RTS hardware flow control enable. If this bit is set to 1, RTS hardware flow control is
+        /// enabled. Data is only requested when there is space in the receive FIFO for it to be
+        /// received.
+        RTSEN OFFSET(14) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ],
+
         /// Receive enable. If this bit is set to 1, the receive section of the UART is enabled.
         /// Data reception occurs for either UART signals or SIR signals depending on the setting of
         /// the SIREN bit. When the UART is disabled in the middle of reception, it completes the
@@ -185,6 +333,13 @@ register_bitfields! {
             Enabled = 1
         ],
 
+        /// Loopback enable. If this bit is set to 1, the UARTTXD path is fed through to the UARTRXD
+        /// path.
+        LBE OFFSET(7) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ],
+
         /// Transmit enable. If this bit is set to 1, the transmit section of the UART is enabled.
         /// Data transmission occurs for either UART signals, or SIR signals depending on the
         /// setting of the SIREN bit. When the UART is disabled in the middle of transmission, it
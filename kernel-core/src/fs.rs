@@ -0,0 +1,253 @@
+//! Virtual filesystem, with a single [`ramfs`] mounted at the root and
+//! populated from the initial ramdisk.
+//!
+//! Filesystems implement [`Inode`]. Paths are walked through a cache of
+//! [`Dentry`]s, and opened as [`File`]s with their own offset, which each
+//! process refers to by file descriptor through its [`Files`] table.
+//!
+//! There is no working directory: relative paths start from the root too.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use crate::initrd;
+use crate::sync::Once;
+
+mod console;
+mod dentry;
+mod file;
+pub mod ramfs;
+
+pub use dentry::Dentry;
+pub use file::File;
+pub use file::Files;
+pub use rosin_abi::NAME_MAX;
+pub use rosin_abi::Stat;
+pub use rosin_abi::mode;
+pub use rosin_abi::open as flags;
+pub use rosin_abi::seek;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    NotFound,
+    Exists,
+    NotDirectory,
+    IsDirectory,
+    /// Unsupported by the inode, or an invalid argument
+    Invalid,
+    NameTooLong,
+    /// File descriptor is not open, or not open for the access requested
+    BadFd,
+    TooManyFiles,
+    /// Out of memory to store file data, or past the largest file size
+    NoSpace,
+}
+
+/// Directory entry, as returned by [`Inode::entry`].
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub name: String,
+    pub ino: u64,
+    pub mode: u32,
+}
+
+/// File, directory, or device within a filesystem.
+///
+/// Operations that don't apply to the kind of inode fail by default.
+pub trait Inode: Send + Sync {
+    fn stat(&self) -> Stat;
+
+    /// Read from `offset` into `buffer`, returning 0 at the end of the file.
+    fn read_at(&self, _offset: u64, _buffer: &mut [u8]) -> Result<usize, Error> {
+        Err(Error::Invalid)
+    }
+
+    /// Write `bytes` at `offset`, extending the file if necessary.
+    fn write_at(&self, _offset: u64, _bytes: &[u8]) -> Result<usize, Error> {
+        Err(Error::Invalid)
+    }
+
+    fn truncate(&self, _len: u64) -> Result<(), Error> {
+        Err(Error::Invalid)
+    }
+
+    /// Find the child named `name`.
+    fn lookup(&self, _name: &str) -> Result<Arc<dyn Inode>, Error> {
+        Err(Error::NotDirectory)
+    }
+
+    /// Create a child named `name` of the type given by `mode`.
+    fn create(&self, _name: &str, _mode: u32) -> Result<Arc<dyn Inode>, Error> {
+        Err(Error::NotDirectory)
+    }
+
+    /// Child at `index` in a stable order, or `None` past the last.
+    fn entry(&self, _index: usize) -> Result<Option<Entry>, Error> {
+        Err(Error::NotDirectory)
+    }
+
+    fn is_dir(&self) -> bool {
+        self.stat().mode & mode::TYPE == mode::DIRECTORY
+    }
+}
+
+static ROOT: Once<Arc<Dentry>> = Once::new();
+
+/// Mount an empty ramfs at the root, and copy in the initial ramdisk if the
+/// bootloader was given one.
+pub fn init() {
+    let root = ROOT.call_once(|| Dentry::root(ramfs::Directory::new(0o755)));
+
+    let Some(archive) = initrd::archive() else {
+        return;
+    };
+
+    let mut files = 0;
+    for entry in archive.iter() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                warn!("Malformed initrd: {:?}", error);
+                break;
+            }
+        };
+
+        // The root itself
+        if entry.path().is_empty() {
+            continue;
+        }
+
+        match populate(root, &entry) {
+            Ok(()) => files += 1,
+            Err(error) => warn!("Skipping initrd entry {:?}: {:?}", entry, error),
+        }
+    }
+
+    info!("Copied {} entries from initrd", files);
+}
+
+/// Create `entry` and any missing parent directories under `root`.
+fn populate(root: &Arc<Dentry>, entry: &crate::cpio::Entry) -> Result<(), Error> {
+    let mut components = components(entry.path()).peekable();
+    let mut dentry = root.clone();
+
+    while let Some(name) = components.next() {
+        let last = components.peek().is_none();
+        let kind = match last {
+            true => entry.mode() & mode::TYPE,
+            false => mode::DIRECTORY,
+        };
+
+        dentry = match dentry.lookup(name) {
+            Ok(child) if kind == mode::DIRECTORY && child.inode().is_dir() => child,
+            Ok(_) => return Err(Error::Exists),
+            Err(Error::NotFound) if kind == mode::DIRECTORY && !last => {
+                dentry.create(name, mode::DIRECTORY | 0o755)?
+            }
+            Err(Error::NotFound) if kind == mode::DIRECTORY || kind == mode::FILE => {
+                dentry.create(name, entry.mode())?
+            }
+            Err(Error::NotFound) => return Err(Error::Invalid),
+            Err(error) => return Err(error),
+        };
+    }
+
+    if entry.is_file() {
+        dentry.inode().write_at(0, entry.data())?;
+    }
+
+    Ok(())
+}
+
+pub fn root() -> &'static Arc<Dentry> {
+    ROOT.get().expect("Filesystem not initialized")
+}
+
+/// Walk `path` from the root, following `.` and `..`.
+pub fn lookup(path: &str) -> Result<Arc<Dentry>, Error> {
+    components(path).try_fold(root().clone(), |dentry, name| walk(&dentry, name))
+}
+
+/// Walk to the parent directory of `path`, returning it and the final
+/// component, which must be a name rather than `.` or `..`.
+fn lookup_parent(path: &str) -> Result<(Arc<Dentry>, &str), Error> {
+    let path = path.trim_end_matches('/');
+    let (parent, name) = match path.rsplit_once('/') {
+        None => (root().clone(), path),
+        Some((parent, name)) => (lookup(parent)?, name),
+    };
+
+    match name {
+        "" | "." | ".." => Err(Error::Exists),
+        name if name.len() > NAME_MAX => Err(Error::NameTooLong),
+        name => Ok((parent, name)),
+    }
+}
+
+fn walk(dentry: &Arc<Dentry>, name: &str) -> Result<Arc<Dentry>, Error> {
+    match name {
+        "." => Ok(dentry.clone()),
+        ".." => Ok(dentry.parent()),
+        name if name.len() > NAME_MAX => Err(Error::NameTooLong),
+        name => dentry.lookup(name),
+    }
+}
+
+fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|name| !name.is_empty())
+}
+
+/// Open `path` with `flags` (see [`flags`]), creating a regular file if
+/// requested.
+pub fn open(path: &str, flags: u64) -> Result<Arc<File>, Error> {
+    let dentry = match lookup(path) {
+        Ok(_) if flags & flags::CREATE != 0 && flags & flags::EXCLUSIVE != 0 => {
+            return Err(Error::Exists);
+        }
+        Ok(dentry) => dentry,
+        Err(Error::NotFound) if flags & flags::CREATE != 0 => {
+            let (parent, name) = lookup_parent(path)?;
+            parent.create(name, mode::FILE | 0o644)?
+        }
+        Err(error) => return Err(error),
+    };
+
+    let writable = flags & flags::ACCESS != flags::READ_ONLY;
+    match dentry.inode().is_dir() {
+        true if writable => return Err(Error::IsDirectory),
+        false if flags & flags::DIRECTORY != 0 => return Err(Error::NotDirectory),
+        _ => (),
+    }
+
+    if writable && flags & flags::TRUNCATE != 0 {
+        dentry.inode().truncate(0)?;
+    }
+
+    Ok(Arc::new(File::new(dentry, flags)))
+}
+
+/// Read the whole file at `path`.
+pub fn read(path: &str) -> Result<Vec<u8>, Error> {
+    let file = open(path, flags::READ_ONLY)?;
+    let mut data = alloc::vec![0; file.dentry().inode().stat().size as usize];
+    let mut len = 0;
+    while len < data.len() {
+        match file.read(&mut data[len..])? {
+            0 => break,
+            read => len += read,
+        }
+    }
+    data.truncate(len);
+    Ok(data)
+}
+
+/// Create a directory at `path`, whose parent must already exist.
+pub fn mkdir(path: &str) -> Result<Arc<Dentry>, Error> {
+    let (parent, name) = lookup_parent(path)?;
+    parent.create(name, mode::DIRECTORY | 0o755)
+}
+
+pub fn stat(path: &str) -> Result<Stat, Error> {
+    lookup(path).map(|dentry| dentry.inode().stat())
+}
@@ -0,0 +1,42 @@
+use super::Error;
+use super::Inode;
+use super::Stat;
+use super::mode;
+
+/// Console through the mini UART, which ignores offsets.
+pub(super) struct Console;
+
+impl Inode for Console {
+    fn stat(&self) -> Stat {
+        Stat {
+            ino: 0,
+            mode: mode::CHARACTER | 0o620,
+            nlink: 1,
+            size: 0,
+        }
+    }
+
+    /// Block for the first byte only, then take whatever else has arrived.
+    fn read_at(&self, _: u64, buffer: &mut [u8]) -> Result<usize, Error> {
+        let Some((first, rest)) = buffer.split_first_mut() else {
+            return Ok(0);
+        };
+
+        *first = crate::read_byte();
+        let mut read = 1;
+        for byte in rest {
+            match crate::try_read_byte() {
+                Some(next) => *byte = next,
+                None => break,
+            }
+            read += 1;
+        }
+
+        Ok(read)
+    }
+
+    fn write_at(&self, _: u64, bytes: &[u8]) -> Result<usize, Error> {
+        crate::write_bytes(bytes);
+        Ok(bytes.len())
+    }
+}
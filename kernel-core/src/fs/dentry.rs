@@ -0,0 +1,116 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::string::ToString as _;
+use alloc::sync::Arc;
+use alloc::sync::Weak;
+use core::fmt::Debug;
+
+use super::Error;
+use super::Inode;
+use crate::sync::Mutex;
+
+/// Named reference to an inode within the tree, caching the children that
+/// have been looked up through it.
+///
+/// Each dentry keeps its parent alive, but not its children, so unused
+/// branches of the cache are freed.
+pub struct Dentry {
+    name: String,
+    inode: Arc<dyn Inode>,
+    /// `None` for the root, or for files outside the tree like the console
+    parent: Option<Arc<Dentry>>,
+    children: Mutex<BTreeMap<String, Weak<Dentry>>>,
+}
+
+impl Dentry {
+    pub(super) fn root(inode: Arc<dyn Inode>) -> Arc<Self> {
+        Self::detached("", inode)
+    }
+
+    /// Dentry with no parent, which is its own `..`.
+    pub(super) fn detached(name: &str, inode: Arc<dyn Inode>) -> Arc<Self> {
+        Arc::new(Self {
+            name: name.to_string(),
+            inode,
+            parent: None,
+            children: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn inode(&self) -> &Arc<dyn Inode> {
+        &self.inode
+    }
+
+    pub fn parent(self: &Arc<Self>) -> Arc<Self> {
+        self.parent.clone().unwrap_or_else(|| self.clone())
+    }
+
+    /// Find the child named `name`, consulting the cache first.
+    pub fn lookup(self: &Arc<Self>, name: &str) -> Result<Arc<Self>, Error> {
+        let mut children = self.children.lock();
+        if let Some(child) = children.get(name).and_then(Weak::upgrade) {
+            return Ok(child);
+        }
+
+        let inode = self.inode.lookup(name)?;
+        Ok(self.insert(&mut children, name, inode))
+    }
+
+    /// Create a child named `name` of the type given by `mode`.
+    pub fn create(self: &Arc<Self>, name: &str, mode: u32) -> Result<Arc<Self>, Error> {
+        if name.len() > super::NAME_MAX {
+            return Err(Error::NameTooLong);
+        }
+
+        let mut children = self.children.lock();
+        let inode = self.inode.create(name, mode)?;
+        Ok(self.insert(&mut children, name, inode))
+    }
+
+    fn insert(
+        self: &Arc<Self>,
+        children: &mut BTreeMap<String, Weak<Dentry>>,
+        name: &str,
+        inode: Arc<dyn Inode>,
+    ) -> Arc<Self> {
+        let child = Arc::new(Self {
+            name: name.to_string(),
+            inode,
+            parent: Some(self.clone()),
+            children: Mutex::new(BTreeMap::new()),
+        });
+
+        children.retain(|_, child| child.strong_count() > 0);
+        children.insert(name.to_string(), Arc::downgrade(&child));
+        child
+    }
+
+    /// Absolute path from the root, or just the name if detached.
+    pub fn path(&self) -> String {
+        match &self.parent {
+            None if self.name.is_empty() => String::from("/"),
+            None => self.name.clone(),
+            Some(parent) => {
+                let mut path = parent.path();
+                if !path.ends_with('/') {
+                    path.push('/');
+                }
+                path.push_str(&self.name);
+                path
+            }
+        }
+    }
+}
+
+impl Debug for Dentry {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Dentry")
+            .field("path", &self.path())
+            .field("stat", &self.inode.stat())
+            .finish()
+    }
+}
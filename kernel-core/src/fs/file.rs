@@ -0,0 +1,161 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use super::Dentry;
+use super::Entry;
+use super::Error;
+use super::flags;
+use super::seek;
+use crate::sync::Mutex;
+
+/// Most file descriptors open at once per process.
+const MAX_FILES: usize = 64;
+
+/// Open file, shared by every file descriptor referring to it.
+pub struct File {
+    dentry: Arc<Dentry>,
+    flags: u64,
+    /// Byte offset, or index of the next entry for directories
+    offset: Mutex<u64>,
+}
+
+impl File {
+    pub(super) fn new(dentry: Arc<Dentry>, flags: u64) -> Self {
+        Self {
+            dentry,
+            flags,
+            offset: Mutex::new(0),
+        }
+    }
+
+    pub fn dentry(&self) -> &Arc<Dentry> {
+        &self.dentry
+    }
+
+    fn readable(&self) -> bool {
+        self.flags & flags::ACCESS != flags::WRITE_ONLY
+    }
+
+    fn writable(&self) -> bool {
+        matches!(
+            self.flags & flags::ACCESS,
+            flags::WRITE_ONLY | flags::READ_WRITE
+        )
+    }
+
+    /// Read into `buffer` from the current offset, advancing it.
+    pub fn read(&self, buffer: &mut [u8]) -> Result<usize, Error> {
+        if !self.readable() {
+            return Err(Error::BadFd);
+        }
+
+        let mut offset = self.offset.lock();
+        let read = self.dentry.inode().read_at(*offset, buffer)?;
+        *offset += read as u64;
+        Ok(read)
+    }
+
+    /// Write `bytes` at the current offset, or the end of the file if opened
+    /// with [`flags::APPEND`], advancing it.
+    pub fn write(&self, bytes: &[u8]) -> Result<usize, Error> {
+        if !self.writable() {
+            return Err(Error::BadFd);
+        }
+
+        let mut offset = self.offset.lock();
+        if self.flags & flags::APPEND != 0 {
+            *offset = self.dentry.inode().stat().size;
+        }
+
+        let written = self.dentry.inode().write_at(*offset, bytes)?;
+        *offset += written as u64;
+        Ok(written)
+    }
+
+    /// Move the offset relative to `whence` (see [`seek`]), returning it.
+    pub fn seek(&self, delta: i64, whence: u64) -> Result<u64, Error> {
+        let mut offset = self.offset.lock();
+        let base = match whence {
+            seek::SET => 0,
+            seek::CURRENT => *offset,
+            seek::END => self.dentry.inode().stat().size,
+            _ => return Err(Error::Invalid),
+        };
+
+        *offset = base.checked_add_signed(delta).ok_or(Error::Invalid)?;
+        Ok(*offset)
+    }
+
+    /// Next entry of a directory, or `None` past the last.
+    pub fn read_dir(&self) -> Result<Option<Entry>, Error> {
+        if !self.readable() {
+            return Err(Error::BadFd);
+        }
+
+        let mut offset = self.offset.lock();
+        let entry = self.dentry.inode().entry(*offset as usize)?;
+        if entry.is_some() {
+            *offset += 1;
+        }
+        Ok(entry)
+    }
+}
+
+impl Debug for File {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("File")
+            .field("path", &self.dentry.path())
+            .field("flags", &format_args!("{:#o}", self.flags))
+            .finish_non_exhaustive()
+    }
+}
+
+/// File descriptor table of a process.
+#[derive(Debug)]
+pub struct Files(Vec<Option<Arc<File>>>);
+
+impl Files {
+    /// Table with standard input, output, and error open to the console.
+    pub fn console() -> Self {
+        let console = Arc::new(File::new(
+            Dentry::detached("console", Arc::new(super::console::Console)),
+            flags::READ_WRITE,
+        ));
+
+        Self(alloc::vec![
+            Some(console.clone()),
+            Some(console.clone()),
+            Some(console),
+        ])
+    }
+
+    /// Open `file` at the lowest free file descriptor, returning it.
+    pub fn insert(&mut self, file: Arc<File>) -> Result<u64, Error> {
+        if let Some(fd) = self.0.iter().position(Option::is_none) {
+            self.0[fd] = Some(file);
+            return Ok(fd as u64);
+        }
+
+        if self.0.len() >= MAX_FILES {
+            return Err(Error::TooManyFiles);
+        }
+
+        self.0.push(Some(file));
+        Ok(self.0.len() as u64 - 1)
+    }
+
+    pub fn get(&self, fd: u64) -> Result<Arc<File>, Error> {
+        self.0
+            .get(fd as usize)
+            .and_then(Option::clone)
+            .ok_or(Error::BadFd)
+    }
+
+    pub fn remove(&mut self, fd: u64) -> Result<Arc<File>, Error> {
+        self.0
+            .get_mut(fd as usize)
+            .and_then(Option::take)
+            .ok_or(Error::BadFd)
+    }
+}
@@ -0,0 +1,170 @@
+//! Filesystem kept entirely in memory, which is lost on reboot.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::string::ToString as _;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+
+use super::Entry;
+use super::Error;
+use super::Inode;
+use super::Stat;
+use super::mode;
+use crate::sync::Mutex;
+
+/// Largest regular file, in bytes, which bounds the kernel heap a single
+/// write can claim.
+pub const MAX_SIZE: usize = 1 << 26;
+
+/// Inode numbers are unique across every ramfs, and never reused.
+fn ino() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+pub struct File {
+    ino: u64,
+    mode: u32,
+    data: Mutex<Vec<u8>>,
+}
+
+impl File {
+    /// Empty regular file with permission bits from `mode`.
+    pub fn new(mode: u32) -> Arc<Self> {
+        Arc::new(Self {
+            ino: ino(),
+            mode: mode::FILE | (mode & !mode::TYPE),
+            data: Mutex::new(Vec::new()),
+        })
+    }
+}
+
+impl Inode for File {
+    fn stat(&self) -> Stat {
+        Stat {
+            ino: self.ino,
+            mode: self.mode,
+            nlink: 1,
+            size: self.data.lock().len() as u64,
+        }
+    }
+
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize, Error> {
+        let data = self.data.lock();
+        let start = (offset as usize).min(data.len());
+        let len = buffer.len().min(data.len() - start);
+        buffer[..len].copy_from_slice(&data[start..start + len]);
+        Ok(len)
+    }
+
+    /// Writing past the end leaves a hole of zeroes.
+    fn write_at(&self, offset: u64, bytes: &[u8]) -> Result<usize, Error> {
+        let end = offset
+            .checked_add(bytes.len() as u64)
+            .filter(|end| *end <= MAX_SIZE as u64)
+            .ok_or(Error::NoSpace)? as usize;
+        let offset = offset as usize;
+
+        let mut data = self.data.lock();
+        if data.len() < end {
+            resize(&mut data, end)?;
+        }
+        data[offset..end].copy_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn truncate(&self, len: u64) -> Result<(), Error> {
+        if len > MAX_SIZE as u64 {
+            return Err(Error::NoSpace);
+        }
+
+        resize(&mut self.data.lock(), len as usize)
+    }
+}
+
+/// Resize `data` to `len`, zero-filling, without aborting if the heap is
+/// exhausted.
+fn resize(data: &mut Vec<u8>, len: usize) -> Result<(), Error> {
+    data.try_reserve(len.saturating_sub(data.len()))
+        .map_err(|_| Error::NoSpace)?;
+    data.resize(len, 0);
+    Ok(())
+}
+
+pub struct Directory {
+    ino: u64,
+    mode: u32,
+    children: Mutex<BTreeMap<String, Arc<dyn Inode>>>,
+}
+
+impl Directory {
+    /// Empty directory with permission bits from `mode`.
+    pub fn new(mode: u32) -> Arc<Self> {
+        Arc::new(Self {
+            ino: ino(),
+            mode: mode::DIRECTORY | (mode & !mode::TYPE),
+            children: Mutex::new(BTreeMap::new()),
+        })
+    }
+}
+
+impl Inode for Directory {
+    fn stat(&self) -> Stat {
+        Stat {
+            ino: self.ino,
+            mode: self.mode,
+            nlink: 1,
+            size: self.children.lock().len() as u64,
+        }
+    }
+
+    fn read_at(&self, _: u64, _: &mut [u8]) -> Result<usize, Error> {
+        Err(Error::IsDirectory)
+    }
+
+    fn write_at(&self, _: u64, _: &[u8]) -> Result<usize, Error> {
+        Err(Error::IsDirectory)
+    }
+
+    fn truncate(&self, _: u64) -> Result<(), Error> {
+        Err(Error::IsDirectory)
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, Error> {
+        self.children
+            .lock()
+            .get(name)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    fn create(&self, name: &str, mode: u32) -> Result<Arc<dyn Inode>, Error> {
+        let mut children = self.children.lock();
+        if children.contains_key(name) {
+            return Err(Error::Exists);
+        }
+
+        let inode: Arc<dyn Inode> = match mode & mode::TYPE {
+            mode::DIRECTORY => Directory::new(mode),
+            mode::FILE => File::new(mode),
+            _ => return Err(Error::Invalid),
+        };
+
+        children.insert(name.to_string(), inode.clone());
+        Ok(inode)
+    }
+
+    fn entry(&self, index: usize) -> Result<Option<Entry>, Error> {
+        Ok(self.children.lock().iter().nth(index).map(|(name, inode)| {
+            let stat = inode.stat();
+            Entry {
+                name: name.clone(),
+                ino: stat.ino,
+                mode: stat.mode,
+            }
+        }))
+    }
+}
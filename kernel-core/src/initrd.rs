@@ -0,0 +1,28 @@
+//! Initial ramdisk, a cpio archive sent by `chainload-push --initrd` and
+//! placed in memory by `chainload-pull`, which passes its physical range to
+//! the kernel.
+
+use core::ops::Range;
+
+use crate::cpio;
+use crate::mem::Phys;
+use crate::sync::Once;
+
+static INITRD: Once<&'static [u8]> = Once::new();
+
+/// Record the initial ramdisk at `range`, which must be reserved from the
+/// page allocator (see [`crate::mem::init`]). Does nothing if `range` is empty.
+pub fn init(range: Range<Phys>) {
+    let len = (u64::from(range.end) - u64::from(range.start)) as usize;
+    if len == 0 {
+        return;
+    }
+
+    let bytes = unsafe { core::slice::from_raw_parts(range.start.to_virt().as_ptr::<u8>(), len) };
+    INITRD.call_once(|| bytes);
+}
+
+/// Initial ramdisk, if the bootloader was given one.
+pub fn archive() -> Option<cpio::Archive<'static>> {
+    INITRD.get().map(|bytes| cpio::Archive::new(bytes))
+}
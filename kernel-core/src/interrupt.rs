@@ -1,14 +1,23 @@
 use core::arch::global_asm;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
 use core::time::Duration;
 
 use aarch64_cpu::registers::CNTP_CTL_EL0;
 use aarch64_cpu::registers::CNTP_TVAL_EL0;
 use aarch64_cpu::registers::DAIF;
+use aarch64_cpu::registers::ELR_EL1;
+use aarch64_cpu::registers::ESR_EL1;
+use aarch64_cpu::registers::FAR_EL1;
 use aarch64_cpu::registers::ReadWriteable as _;
+use aarch64_cpu::registers::Readable as _;
+use aarch64_cpu::registers::SPSR_EL1;
 use aarch64_cpu::registers::VBAR_EL1;
 use tock_registers::interfaces::Writeable as _;
 
 use crate::device::bcm2837b0;
+use crate::mem::User;
+use crate::mem::Virt;
 use crate::time;
 
 global_asm! {
@@ -62,6 +71,30 @@ r#"
     eret
 .endmacro
 
+.macro USER_ENTER
+    sub sp, sp, {FRAME_SIZE}
+    stp x0, x1, [sp, 16 * 0]
+    stp x2, x3, [sp, 16 * 1]
+    stp x4, x5, [sp, 16 * 2]
+    stp x6, x7, [sp, 16 * 3]
+    stp x8, x9, [sp, 16 * 4]
+    stp x10, x11, [sp, 16 * 5]
+    stp x12, x13, [sp, 16 * 6]
+    stp x14, x15, [sp, 16 * 7]
+    stp x16, x17, [sp, 16 * 8]
+    stp x18, x19, [sp, 16 * 9]
+    stp x20, x21, [sp, 16 * 10]
+    stp x22, x23, [sp, 16 * 11]
+    stp x24, x25, [sp, 16 * 12]
+    stp x26, x27, [sp, 16 * 13]
+    stp x28, x29, [sp, 16 * 14]
+    mrs x9, SP_EL0
+    stp x30, x9, [sp, 16 * 15]
+    mrs x9, ELR_EL1
+    mrs x10, SPSR_EL1
+    stp x9, x10, [sp, 16 * 16]
+.endmacro
+
 .macro VECTOR label
     .align 7
     b \label
@@ -74,13 +107,13 @@ __VECTOR_TABLE:
     VECTOR irq_invalid
     VECTOR irq_invalid
 
-    VECTOR irq_invalid
+    VECTOR sync_el1h
     VECTOR irq_el1t
     VECTOR irq_invalid
     VECTOR irq_invalid
 
-    VECTOR irq_invalid
-    VECTOR irq_invalid
+    VECTOR sync_el0
+    VECTOR irq_el0
     VECTOR irq_invalid
     VECTOR irq_invalid
 
@@ -89,11 +122,101 @@ __VECTOR_TABLE:
     VECTOR irq_invalid
     VECTOR irq_invalid
 
+# Check for kernel stack overflow before touching the stack, since the
+# exception would otherwise recurse on the guard page. Kernel stacks are
+# aligned such that bit {SHIFT} of the stack pointer is set exactly when it
+# lies within the stack rather than its guard (see `mem::stack`). Swaps sp
+# and x0 through arithmetic to test the new stack pointer without a scratch
+# register.
+sync_el1h:
+    sub sp, sp, 16 * 16
+    add sp, sp, x0
+    sub x0, sp, x0
+    tbnz x0, {SHIFT}, 1f
+    sub x0, sp, x0
+    sub sp, sp, x0
+    b stack_overflow
+1:
+    sub x0, sp, x0
+    sub sp, sp, x0
+    add sp, sp, 16 * 16
+
+    IRQ_ENTER
+    bl handle_sync_el1h
+    b irq_spin
+
+# Switch to this core's exception stack. The overflowing context's registers
+# are lost, but ELR_EL1 and FAR_EL1 still identify the fault.
+stack_overflow:
+    mrs x0, MPIDR_EL1
+    and x0, x0, 0b11
+    add x0, x0, 1
+    mov x1, {EXCEPTION_STACK_SIZE}
+    ldr x2, =__EXCEPTION_STACKS
+    madd x0, x0, x1, x2
+    mov sp, x0
+    bl handle_stack_overflow
+    b irq_spin
+
+# Saves the exception return state as well, since the handler may switch
+# threads (see `thread::irq_exit`) and take further exceptions.
 irq_el1t:
     IRQ_ENTER
+    mrs x0, ELR_EL1
+    mrs x1, SPSR_EL1
+    PUSH x0, x1
+
     bl handle_irq_el1t
+    bl handle_irq_exit
+
+    POP x0, x1
+    msr ELR_EL1, x0
+    msr SPSR_EL1, x1
     IRQ_LEAVE
 
+# Exceptions from EL0 arrive on the current thread's kernel stack, and save
+# the user context as a `Frame` there.
+sync_el0:
+    USER_ENTER
+    mov x0, sp
+    bl handle_sync_el0
+    bl handle_irq_exit
+    b user_leave
+
+irq_el0:
+    USER_ENTER
+    bl handle_irq_el1t
+    bl handle_irq_exit
+    b user_leave
+
+# Return to EL0 with the `Frame` at sp.
+.global _enter_user
+_enter_user:
+    mov sp, x0
+user_leave:
+    ldp x9, x10, [sp, 16 * 16]
+    msr ELR_EL1, x9
+    msr SPSR_EL1, x10
+    ldp x30, x9, [sp, 16 * 15]
+    msr SP_EL0, x9
+    ldp x0, x1, [sp, 16 * 0]
+    ldp x2, x3, [sp, 16 * 1]
+    ldp x4, x5, [sp, 16 * 2]
+    ldp x6, x7, [sp, 16 * 3]
+    ldp x8, x9, [sp, 16 * 4]
+    ldp x10, x11, [sp, 16 * 5]
+    ldp x12, x13, [sp, 16 * 6]
+    ldp x14, x15, [sp, 16 * 7]
+    ldp x16, x17, [sp, 16 * 8]
+    ldp x18, x19, [sp, 16 * 9]
+    ldp x20, x21, [sp, 16 * 10]
+    ldp x22, x23, [sp, 16 * 11]
+    ldp x24, x25, [sp, 16 * 12]
+    ldp x26, x27, [sp, 16 * 13]
+    ldp x28, x29, [sp, 16 * 14]
+    add sp, sp, {FRAME_SIZE}
+    eret
+
 irq_invalid:
     IRQ_ENTER
     bl handle_irq_invalid
@@ -106,24 +229,109 @@ irq_spin:
 .size __VECTOR_TABLE, . - __VECTOR_TABLE
 
 .popsection
-"#
+
+.pushsection .bss.__EXCEPTION_STACKS, "aw", %nobits
+.align 4
+__EXCEPTION_STACKS:
+    .space {EXCEPTION_STACK_SIZE} * {CPUS}
+.popsection
+"#,
+    SHIFT = const crate::mem::stack::SHIFT,
+    FRAME_SIZE = const size_of::<Frame>(),
+    EXCEPTION_STACK_SIZE = const EXCEPTION_STACK_SIZE,
+    CPUS = const crate::smp::CPUS,
+}
+
+/// User context saved on exception entry from EL0, and restored on return.
+#[repr(C)]
+#[derive(Clone, Debug, Default)]
+pub struct Frame {
+    /// General purpose registers `x0` through `x30`
+    pub x: [u64; 31],
+    /// `SP_EL0`
+    pub sp: u64,
+    /// `ELR_EL1`, the address to return to
+    pub elr: u64,
+    /// `SPSR_EL1`, the saved processor state
+    pub spsr: u64,
+}
+
+const _: () = assert!(size_of::<Frame>() % 16 == 0);
+
+impl Frame {
+    /// Context that starts executing at `entry` in EL0 with stack pointer
+    /// `sp` and interrupts unmasked.
+    pub fn user(entry: Virt<User>, sp: Virt<User>) -> Self {
+        Self {
+            elr: u64::from(entry),
+            sp: u64::from(sp),
+            spsr: SPSR_EL1::M::EL0t.value,
+            ..Default::default()
+        }
+    }
 }
 
+unsafe extern "C" {
+    /// Restore `frame` and return to EL0, abandoning the current kernel stack
+    /// above `frame`, which must lie on the current thread's kernel stack.
+    fn _enter_user(frame: *const Frame) -> !;
+}
+
+/// Switch to EL0 with the context in `frame`. Exceptions from EL0 enter the
+/// kernel on the current thread's kernel stack.
+pub fn enter_user(frame: Frame) -> ! {
+    let _ = disable();
+    unsafe { _enter_user(&frame) }
+}
+
+/// Bytes of stack per core for handling kernel stack overflow.
+const EXCEPTION_STACK_SIZE: usize = 1 << 14;
+
 unsafe extern "C" {
     static __VECTOR_TABLE: u32;
 }
 
 pub unsafe fn init() {
+    unsafe { bcm2837b0::ic::Peripheral::new(0x3F00_B000) }.init();
+    unsafe { init_cpu() };
+}
+
+/// Install the exception vectors and route the timer interrupt for the current core.
+///
+/// # Safety
+///
+/// Caller must guarantee per-CPU data is initialized, and that the core is
+/// ready to take exceptions.
+pub unsafe fn init_cpu() {
     VBAR_EL1.set(unsafe { &__VECTOR_TABLE as *const _ as u64 });
 
-    unsafe { bcm2837b0::ic::Peripheral::new(0x3F00_B000) }.init();
-    unsafe { bcm2837b0::ic::Core::new(0x4000_0000) }.init();
+    unsafe { bcm2837b0::ic::Core::new(0x4000_0000) }.init(crate::smp::cpu_id());
 }
 
 pub fn enable() {
     DAIF.write(DAIF::D::Unmasked + DAIF::A::Unmasked + DAIF::I::Unmasked + DAIF::F::Unmasked)
 }
 
+/// Interrupt masks saved by [`disable`].
+#[must_use]
+pub struct Mask(u64);
+
+/// Mask IRQs on the current core, returning the previous masks for [`restore`].
+pub fn disable() -> Mask {
+    let mask = Mask(DAIF.get());
+    DAIF.modify(DAIF::I::Masked);
+    mask
+}
+
+pub fn restore(mask: Mask) {
+    DAIF.set(mask.0);
+}
+
+/// Wait for an interrupt.
+pub fn wait() {
+    aarch64_cpu::asm::wfi()
+}
+
 pub fn enable_timer(duration: Duration) {
     CNTP_TVAL_EL0.set(time::Cycle::from(duration).value());
     CNTP_CTL_EL0.modify(CNTP_CTL_EL0::ENABLE::SET + CNTP_CTL_EL0::IMASK::CLEAR);
@@ -131,7 +339,98 @@ pub fn enable_timer(duration: Duration) {
 
 #[unsafe(no_mangle)]
 pub extern "C" fn handle_irq_el1t() {
-    info!("handle",);
+    let source = unsafe { bcm2837b0::ic::Core::new(0x4000_0000) }.source(crate::smp::cpu_id());
+
+    if source.is_set(bcm2837b0::ic::Source::M0) {
+        crate::smp::handle_ipi();
+    }
+
+    if source.is_set(bcm2837b0::ic::Source::CNTPNS) {
+        crate::thread::tick();
+    }
+
+    // Peripheral interrupts are routed to core 0
+    if source.is_set(bcm2837b0::ic::Source::GPU) {
+        let peripheral = unsafe { bcm2837b0::ic::Peripheral::new(0x3F00_B000) };
+
+        if peripheral.is_pending(bcm2837b0::mini::Uart::IRQ) {
+            crate::handle_console_input();
+        }
+
+        if bcm2837b0::ic::Peripheral::IRQ_GPIO
+            .iter()
+            .any(|irq| peripheral.is_pending(*irq))
+        {
+            handle_gpio();
+        }
+    }
+}
+
+/// Handler for GPIO events, stored as a `fn(usize)` pointer (zero if unset).
+static GPIO_HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+/// Call `handler` with the pin number of each GPIO event latched by
+/// [`bcm2837b0::gpio::Gpio::enable_detect`], once GPIO interrupts are enabled
+/// with [`bcm2837b0::ic::Peripheral::enable_irq`]. Replaces any previously
+/// registered handler.
+pub fn register_gpio(handler: fn(usize)) {
+    GPIO_HANDLER.store(handler as usize, Ordering::Release);
+}
+
+/// Acknowledge every latched GPIO event, which deasserts the interrupt, and
+/// dispatch it to the registered handler.
+fn handle_gpio() {
+    let handler = GPIO_HANDLER.load(Ordering::Acquire);
+    let gpio = unsafe { bcm2837b0::gpio::Gpio::new(0x3F20_0000) };
+
+    for pin in gpio.drain_events() {
+        if handler == 0 {
+            warn!("Unhandled event on GPIO {}", pin);
+            continue;
+        }
+
+        let handler = unsafe { core::mem::transmute::<usize, fn(usize)>(handler) };
+        handler(pin);
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn handle_irq_exit() {
+    crate::thread::irq_exit();
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn handle_sync_el1h() -> ! {
+    panic!(
+        "Unhandled synchronous exception at {:#x}: ESR {:#x}, FAR {:#x}",
+        ELR_EL1.get(),
+        ESR_EL1.get(),
+        FAR_EL1.get(),
+    )
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn handle_sync_el0(frame: &mut Frame) {
+    let esr = ESR_EL1.get();
+    if !ESR_EL1.matches_all(ESR_EL1::EC::SVC64) {
+        crate::process::fault(frame, esr, FAR_EL1.get())
+    }
+
+    // System calls may block, so run them with interrupts enabled, but
+    // return to the exception vector with them masked again
+    enable();
+    crate::syscall::dispatch(frame);
+    let _ = disable();
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn handle_stack_overflow() -> ! {
+    panic!(
+        "Kernel stack overflow at {:#x}: ESR {:#x}, FAR {:#x}",
+        ELR_EL1.get(),
+        ESR_EL1.get(),
+        FAR_EL1.get(),
+    )
 }
 
 #[unsafe(no_mangle)]
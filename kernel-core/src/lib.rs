@@ -1,23 +1,39 @@
 #![no_std]
 
+extern crate alloc;
+
 #[macro_use]
 pub mod print;
 
+#[macro_use]
+pub mod boot;
+
 pub mod bitset;
+pub mod cpio;
 pub mod device;
+pub mod fs;
+pub mod initrd;
 pub mod interrupt;
 pub mod mem;
 pub mod mmu;
-mod sync;
+pub mod process;
+pub mod smp;
+pub mod sync;
+pub mod syscall;
+pub mod thread;
 pub mod time;
 pub mod unit;
 
 use core::fmt::Debug;
 use core::fmt::Write;
 use core::panic::PanicInfo;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering;
 
 use aarch64_cpu::asm;
-use sync::SpinLock;
+use device::bcm2837b0::uart;
+use sync::Channel;
+use sync::IrqSpinLock;
 
 #[inline]
 pub fn pause() {
@@ -33,20 +49,50 @@ pub fn spin() -> ! {
 
 #[doc(hidden)]
 pub fn _print(args: core::fmt::Arguments) {
-    // UART.lock().write_fmt(args).unwrap();
-    // UART_MINI.lock().write_fmt(args).unwrap();
-    unsafe { device::bcm2837b0::mini::Uart::new(0x3F21_5000) }
-        .write_fmt(args)
-        .unwrap();
+    if CONSOLE_PL011.load(Ordering::Acquire) {
+        UART.lock().write_fmt(args).unwrap();
+        return;
+    }
+
+    UART_MINI.lock().write_fmt(args).unwrap();
+}
+
+/// Write raw bytes to the console, which need not be valid UTF-8.
+pub fn write_bytes(bytes: &[u8]) {
+    if CONSOLE_PL011.load(Ordering::Acquire) {
+        let mut uart = UART.lock();
+        bytes.iter().for_each(|byte| uart.write_byte(*byte));
+        return;
+    }
+
+    let mut uart = UART_MINI.lock();
+    bytes.iter().for_each(|byte| uart.write_byte(*byte));
+}
+
+static CONSOLE_PL011: AtomicBool = AtomicBool::new(false);
+
+/// Switch the console from the mini UART to the PL011 UART.
+pub fn init_console_pl011(config: &uart::Config) {
+    unsafe { device::bcm2837b0::gpio::Gpio::new(0x3F20_0000) }.init_pl011(config.rts_cts);
+
+    let clock = unsafe { device::bcm2837b0::mailbox::Mailbox::new(0x3F00_B880) }
+        .clock_rate(device::bcm2837b0::mailbox::Clock::Uart)
+        .unwrap_or(uart::Uart::DEFAULT_CLOCK);
+
+    UART.lock().initialize(config, clock);
+    CONSOLE_PL011.store(true, Ordering::Release);
 }
 
 pub fn init() {
+    unsafe {
+        smp::init_cpu();
+    }
+
     // unsafe {
     //     bcm2837b0::gpio::Gpio::new(0x3F20_0000).init();
     //     dev::bcm2837b0::mini::Uart::new(0x3F21_5000).init();
     // }
 
-    // UART.lock().initialize();
     // UART_MINI.lock().init();
 
     // unsafe {
@@ -58,11 +104,40 @@ pub fn init() {
     // }
 }
 
-pub static UART: SpinLock<device::bcm2837b0::uart::Uart> =
-    SpinLock::new(unsafe { device::bcm2837b0::uart::Uart::new(0x3F20_1000) });
+pub static UART: IrqSpinLock<uart::Uart> =
+    IrqSpinLock::new(unsafe { uart::Uart::new(0x3F20_1000) });
+
+pub static UART_MINI: IrqSpinLock<device::bcm2837b0::mini::Uart> =
+    IrqSpinLock::new(unsafe { device::bcm2837b0::mini::Uart::new(0x3F21_5000) });
+
+/// Bytes received by the mini UART, queued by its interrupt handler.
+static INPUT: Channel<u8> = Channel::new(256);
+
+/// Receive console input from the mini UART through its interrupt, so that
+/// [`read_byte`] blocks the calling thread instead of polling.
+pub fn init_console_input() {
+    UART_MINI.lock().enable_rx_interrupt();
+    unsafe { device::bcm2837b0::ic::Peripheral::new(0x3F00_B000) }
+        .enable_irq(device::bcm2837b0::mini::Uart::IRQ);
+}
 
-pub static UART_MINI: SpinLock<device::bcm2837b0::mini::Uart> =
-    SpinLock::new(unsafe { device::bcm2837b0::mini::Uart::new(0x3F21_5000) });
+/// Block until a byte of console input arrives (see [`init_console_input`]).
+pub fn read_byte() -> u8 {
+    INPUT.recv()
+}
+
+/// Take a byte of console input if one has arrived, without blocking.
+pub fn try_read_byte() -> Option<u8> {
+    INPUT.try_recv()
+}
+
+/// Drain the mini UART's receive FIFO, dropping input while nobody reads.
+pub(crate) fn handle_console_input() {
+    let mut uart = UART_MINI.lock();
+    while let Some(byte) = uart.try_read_byte() {
+        let _ = INPUT.try_send(byte);
+    }
+}
 
 pub type Result<T> = core::result::Result<T, Error>;
 
@@ -83,11 +158,12 @@ pub struct Console;
 
 impl Write for Console {
     fn write_str(&mut self, string: &str) -> core::fmt::Result {
-        const ADDRESS: *mut u8 = 0x3F20_1000 as _;
+        let address =
+            core::ptr::with_exposed_provenance_mut::<u8>(device::bcm2837b0::mmio(0x3F20_1000));
 
         for byte in string.bytes() {
             unsafe {
-                ADDRESS.write_volatile(byte);
+                address.write_volatile(byte);
             }
         }
 
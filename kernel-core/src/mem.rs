@@ -1,19 +1,41 @@
 use core::fmt::Debug;
 use core::marker::PhantomData;
+use core::ops::Range;
+
+use arrayvec::ArrayVec;
+
+use crate::device::bcm2837b0::mailbox::Mailbox;
 
 pub mod alloc;
+pub mod heap;
 pub mod page;
+pub mod stack;
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Phys(u64);
 
 impl Phys {
+    /// Largest physical address size supported with a 64KiB granule (without FEAT_LPA).
+    pub const BITS: u32 = 48;
+
     pub const fn new(address: u64) -> Self {
-        assert!(address < (1 << 32));
+        assert!(address < (1 << Self::BITS));
         Self(address)
     }
 }
 
+impl Phys {
+    /// Address of this physical address in the kernel's linear map.
+    pub fn to_virt(self) -> Virt<Kernel> {
+        assert!(
+            self.0 < LINEAR_SIZE,
+            "Physical address {:#x} outside of linear map",
+            self.0,
+        );
+        Virt::new(self.0 + OFFSET)
+    }
+}
+
 impl From<Phys> for u64 {
     fn from(phys: Phys) -> Self {
         phys.0
@@ -22,7 +44,7 @@ impl From<Phys> for u64 {
 
 impl From<page::Id> for Phys {
     fn from(id: page::Id) -> Self {
-        Self::new(u64::from(id) << 16)
+        Self::new(u64::from(id) << PAGE_SHIFT)
     }
 }
 
@@ -49,6 +71,22 @@ impl<S: AddressSpace> Virt<S> {
     }
 }
 
+impl Virt<Kernel> {
+    /// Physical address of this address in the kernel's linear map.
+    pub fn to_phys(self) -> Phys {
+        assert!(
+            self.address - OFFSET < LINEAR_SIZE,
+            "Virtual address {:#x} outside of linear map",
+            self.address,
+        );
+        Phys::new(self.address - OFFSET)
+    }
+
+    pub fn as_ptr<T>(self) -> *mut T {
+        core::ptr::with_exposed_provenance_mut(self.address as usize)
+    }
+}
+
 impl<S> From<Virt<S>> for u64 {
     fn from(virt: Virt<S>) -> Self {
         virt.address
@@ -70,16 +108,23 @@ pub struct User;
 
 #[expect(private_bounds)]
 pub trait AddressSpace: seal::Seal + Copy + Clone + core::fmt::Debug {
+    /// Whether this address space is translated through `TTBR1_EL1`
+    const KERNEL: bool;
+
     fn validate(address: u64) -> bool;
 }
 
 impl AddressSpace for Kernel {
+    const KERNEL: bool = true;
+
     fn validate(address: u64) -> bool {
         address >= OFFSET
     }
 }
 
 impl AddressSpace for User {
+    const KERNEL: bool = false;
+
     fn validate(address: u64) -> bool {
         address < OFFSET
     }
@@ -92,3 +137,133 @@ mod seal {
 }
 
 pub const OFFSET: u64 = 0xFFFF_FFFF_0000_0000;
+
+/// Size of the linear map of physical memory at [`OFFSET`], which contains the
+/// kernel image, all RAM, and device MMIO at their physical offsets.
+pub const LINEAR_SIZE: u64 = 1 << 31;
+
+/// Base 2 logarithm of [`PAGE_SIZE`].
+pub const PAGE_SHIFT: u32 = crate::mmu::Config::DEFAULT.granule.shift();
+
+/// Size of a page under the translation granule in [`crate::mmu::Config::DEFAULT`].
+pub const PAGE_SIZE: u64 = 1 << PAGE_SHIFT;
+
+const _: () = assert!(PAGE_SIZE == rosin_abi::PAGE_SIZE as u64);
+
+/// Seed the global page allocator with RAM from the device tree, excluding the
+/// kernel `image`, the boot page `tables`, the `initrd` (empty if none), the
+/// device tree itself, and firmware reservations. Allocator metadata is placed
+/// directly after `tables`.
+///
+/// Requires the kernel page table to be initialized with [`crate::mmu::init_kernel`].
+pub fn init(
+    device_tree: &device_tree::Blob,
+    image: Range<Phys>,
+    tables: Range<Phys>,
+    initrd: Range<Phys>,
+) {
+    let mut mailbox = unsafe { Mailbox::new(0x3F00_B880) };
+    let arm = mailbox.arm_memory();
+    let vc = mailbox.vc_memory();
+
+    let memory = memory(device_tree);
+
+    let pages = memory
+        .iter()
+        .map(|range| u64::from(range.end))
+        .max()
+        .expect("No memory in device tree")
+        .div_ceil(PAGE_SIZE) as usize;
+
+    // NOTE: page table frames, including the bootstrap identity table that
+    // is still installed in TTBR0 until `mmu::unmap_identity`, end at `tables.end`
+    let metadata = Phys::new(u64::from(tables.end).next_multiple_of(PAGE_SIZE));
+
+    let allocator = alloc::global();
+    unsafe { allocator.init(metadata.to_virt().as_ptr(), pages) };
+
+    let len = |range: &Range<Phys>| (u64::from(range.end) - u64::from(range.start)) as usize;
+
+    let reserved = [
+        (
+            Virt::<Kernel>::new(device_tree.as_ptr().as_ptr() as u64).to_phys(),
+            device_tree.header().len(),
+        ),
+        (image.start, len(&image)),
+        (tables.start, len(&tables)),
+        (initrd.start, len(&initrd)),
+        (metadata, alloc::Page::size_of(pages)),
+    ]
+    .into_iter()
+    .map(|(base, len)| (u64::from(base) as usize, len))
+    .chain(
+        device_tree
+            .reservations()
+            .map(|reg| (reg.address as usize, reg.len as usize)),
+    )
+    .chain(
+        device_tree
+            .root()
+            .reserved_memory()
+            .into_iter()
+            .flat_map(|node| node.children())
+            .filter_map(|node| node.reg())
+            .flat_map(|reg| reg.iter())
+            .map(|reg| (reg.address as usize, reg.len as usize)),
+    )
+    .filter(|(_, len)| *len > 0)
+    .map(|(base, len)| Phys::new(base as u64)..Phys::new((base + len) as u64))
+    .collect::<ArrayVec<_, 32>>();
+
+    info!("Memory map:",);
+    if let Some(arm) = &arm {
+        info!("  ARM      {:#x} - {:#x}", arm.start, arm.end);
+    }
+    if let Some(vc) = &vc {
+        info!("  VC       {:#x} - {:#x}", vc.start, vc.end);
+    }
+    for range in &memory {
+        info!(
+            "  memory   {:#x} - {:#x} ({:#?})",
+            u64::from(range.start),
+            u64::from(range.end),
+            crate::unit::Byte::new(len(range)),
+        );
+    }
+    for range in &reserved {
+        info!(
+            "  reserved {:#x} - {:#x} ({:#?})",
+            u64::from(range.start),
+            u64::from(range.end),
+            crate::unit::Byte::new(len(range)),
+        );
+    }
+
+    allocator.seed(memory.iter().cloned(), reserved.iter().cloned());
+
+    info!("Physical memory: {:#x?}", allocator.dump());
+
+    stack::init();
+}
+
+/// RAM available to the ARM cores, according to the device tree.
+///
+/// Memory above the ARM/VideoCore split belongs to the GPU, even if the
+/// device tree doesn't say so (e.g. a stale or emulator-provided blob).
+pub fn memory(device_tree: &device_tree::Blob) -> ArrayVec<Range<Phys>, 8> {
+    let arm = unsafe { Mailbox::new(0x3F00_B880) }.arm_memory();
+
+    device_tree
+        .root()
+        .memory()
+        .filter_map(|node| node.reg())
+        .flat_map(|reg| reg.iter())
+        .map(|reg| reg.address..reg.address + reg.len)
+        .map(|range| match &arm {
+            None => range,
+            Some(arm) => range.start.max(arm.start)..range.end.min(arm.end),
+        })
+        .filter(|range| !range.is_empty())
+        .map(|range| Phys::new(range.start)..Phys::new(range.end))
+        .collect()
+}
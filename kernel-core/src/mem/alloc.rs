@@ -1,50 +1,311 @@
+use core::fmt::Debug;
+use core::ops::Range;
+use core::sync::atomic::AtomicPtr;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+
 use crate::bitset;
+use crate::mem::PAGE_SHIFT;
+use crate::mem::PAGE_SIZE;
+use crate::mem::Phys;
 use crate::mem::page;
 
-#[repr(transparent)]
-pub struct Page(bitset::Unsized);
+static PAGE: Page = Page::new();
+
+/// Physical page allocator shared by all cores.
+pub fn global() -> &'static Page {
+    &PAGE
+}
+
+/// Allocate a page from the global allocator, zeroed through the linear map.
+pub fn zeroed() -> Option<page::Id> {
+    let page = global().allocate()?;
+    unsafe {
+        core::ptr::write_bytes(
+            Phys::from(page).to_virt().as_ptr::<u8>(),
+            0,
+            PAGE_SIZE as usize,
+        );
+    }
+    Some(page)
+}
+
+/// Number of block sizes, from a single 64KiB page (order 0) up to 64MiB.
+pub const ORDERS: usize = 11;
+
+/// Lock-free binary buddy allocator over physical pages.
+///
+/// Each order keeps a bitset where a set bit marks a free block of `1 << order`
+/// pages. A free block is only ever recorded at a single order, so blocks are
+/// claimed by atomically clearing their bit, and coalesced on free by claiming
+/// the buddy's bit before moving up an order.
+///
+/// Concurrent frees of two buddies may both fail to observe each other and
+/// leave the pair uncoalesced; this only costs fragmentation, never correctness.
+pub struct Page {
+    bits: AtomicPtr<u64>,
+
+    /// Number of pages tracked by this allocator
+    pages: AtomicUsize,
+
+    /// Next-fit hint per order: block index to start the next search from
+    hints: [AtomicUsize; ORDERS],
+}
 
 impl Page {
-    pub const unsafe fn from_raw_parts_mut<'a>(pointer: *mut u64, len: usize) -> &'a mut Self {
-        unsafe {
-            core::mem::transmute(bitset::Unsized::from_raw_parts_mut(
-                pointer.cast::<u64>(),
-                len,
-            ))
+    pub const fn new() -> Self {
+        Self {
+            bits: AtomicPtr::new(core::ptr::null_mut()),
+            pages: AtomicUsize::new(0),
+            hints: [const { AtomicUsize::new(0) }; ORDERS],
         }
     }
 
-    pub const fn size_of(len: usize) -> usize {
-        bitset::Unsized::size_of(len)
+    /// Bytes of metadata required to track `pages` pages.
+    pub const fn size_of(pages: usize) -> usize {
+        bitset::Unsized::size_of(Self::offset(pages, ORDERS))
+    }
+
+    /// # Safety
+    ///
+    /// Caller must guarantee `pointer` is valid for reads and writes of
+    /// [`Page::size_of`]`(pages)` bytes for the rest of the program, and is
+    /// not otherwise accessed.
+    pub unsafe fn init(&self, pointer: *mut u64, pages: usize) {
+        self.pages.store(pages, Ordering::Relaxed);
+        assert!(
+            self.bits
+                .compare_exchange(
+                    core::ptr::null_mut(),
+                    pointer,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                )
+                .is_ok(),
+            "Page allocator already initialized",
+        );
+
+        (0..ORDERS).for_each(|order| self.order(order).clear());
+    }
+
+    /// Free every page in `memory` except those overlapping `reserved`.
+    pub fn seed<M, R>(&self, memory: M, reserved: R)
+    where
+        M: IntoIterator<Item = Range<Phys>>,
+        R: IntoIterator<Item = Range<Phys>> + Clone,
+    {
+        let pages = |range: Range<Phys>| {
+            let start = u64::from(range.start).div_ceil(PAGE_SIZE) as usize;
+            let end = (u64::from(range.end) >> PAGE_SHIFT) as usize;
+            start..end.max(start)
+        };
+
+        // Reserved ranges round outward to whole pages
+        let reserved = || {
+            reserved.clone().into_iter().map(|range| {
+                let start = (u64::from(range.start) >> PAGE_SHIFT) as usize;
+                let end = u64::from(range.end).div_ceil(PAGE_SIZE) as usize;
+                start..end
+            })
+        };
+
+        for region in memory.into_iter().map(pages) {
+            let end = region.end.min(self.capacity());
+            let mut page = region.start;
+
+            while page < end {
+                match reserved().find(|range| range.contains(&page)) {
+                    Some(range) => page = range.end,
+                    None => {
+                        let next = reserved()
+                            .map(|range| range.start)
+                            .filter(|start| *start > page)
+                            .min()
+                            .unwrap_or(end)
+                            .min(end);
+                        self.add(page, next - page);
+                        page = next;
+                    }
+                }
+            }
+        }
     }
 
-    pub fn fill(&mut self) {
-        self.0.fill_mut()
+    /// Total number of pages tracked by this allocator.
+    pub fn capacity(&self) -> usize {
+        self.pages.load(Ordering::Relaxed)
     }
 
-    pub fn len(&mut self) -> usize {
-        self.0.count_ones_mut()
+    /// Number of free pages.
+    pub fn len(&self) -> usize {
+        (0..ORDERS)
+            .map(|order| self.order(order).count_ones() << order)
+            .sum()
     }
 
-    pub fn is_empty(&mut self) -> bool {
+    pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
 
-    pub fn allocate(&mut self) -> Option<page::Id> {
-        let page = self.0.peek_mut()?;
-        self.0.unset_mut(page);
-        Some(page::Id::from_usize(page))
+    pub fn allocate(&self) -> Option<page::Id> {
+        self.allocate_order(0)
     }
 
-    pub fn reserve(&mut self, page: page::Id) {
-        let page = page.into_usize();
-        assert!(self.0.get_mut(page));
-        self.0.unset_mut(page);
+    pub fn deallocate(&self, page: page::Id) {
+        self.deallocate_order(page, 0)
+    }
+
+    /// Allocate a naturally aligned block of `1 << order` pages.
+    pub fn allocate_order(&self, order: usize) -> Option<page::Id> {
+        assert!(order < ORDERS);
+
+        let (found, mut index) = (order..ORDERS).find_map(|found| {
+            let hint = self.hints[found].load(Ordering::Relaxed);
+            let index = self.order(found).unset_first(hint)?;
+            self.hints[found].store(index + 1, Ordering::Relaxed);
+            Some((found, index))
+        })?;
+
+        // Split down to the requested order, freeing the upper half at each level
+        for split in (order..found).rev() {
+            index <<= 1;
+            assert!(!self.order(split).set(index + 1));
+        }
+
+        Some(page::Id::from_usize(index << order))
     }
 
-    pub fn deallocate(&mut self, page: page::Id) {
+    /// Free a block previously returned by [`Page::allocate_order`] with the same `order`.
+    pub fn deallocate_order(&self, page: page::Id, order: usize) {
         let page = page.into_usize();
-        assert!(!self.0.get_mut(page));
-        self.0.set_mut(page);
+        assert!(order < ORDERS);
+        assert_eq!(
+            page % (1 << order),
+            0,
+            "Misaligned block {page:#x} of order {order}"
+        );
+        self.free(page >> order, order);
+    }
+
+    /// Allocate `len` physically contiguous pages, where the first page index
+    /// is a multiple of `align` (which must be a power of two).
+    pub fn allocate_contiguous(&self, len: usize, align: usize) -> Option<page::Id> {
+        assert!(len > 0);
+        assert!(align.is_power_of_two());
+
+        let order = len.max(align).next_power_of_two().trailing_zeros() as usize;
+        if order >= ORDERS {
+            return None;
+        }
+
+        let page = self.allocate_order(order)?;
+
+        // Return the unused tail
+        self.add(page.into_usize() + len, (1 << order) - len);
+        Some(page)
+    }
+
+    pub fn deallocate_contiguous(&self, page: page::Id, len: usize) {
+        self.add(page.into_usize(), len)
+    }
+
+    /// Snapshot of free blocks per order for diagnostics.
+    pub fn dump(&self) -> Dump {
+        let mut free = [0; ORDERS];
+        free.iter_mut()
+            .enumerate()
+            .for_each(|(order, free)| *free = self.order(order).count_ones());
+        Dump {
+            free,
+            capacity: self.capacity(),
+        }
+    }
+
+    /// Free an arbitrary run of pages as maximal aligned blocks.
+    fn add(&self, mut page: usize, len: usize) {
+        let end = page + len;
+        while page < end {
+            let order = (0..ORDERS)
+                .rev()
+                .find(|order| page % (1 << order) == 0 && page + (1 << order) <= end)
+                .unwrap();
+
+            self.free(page >> order, order);
+            page += 1 << order;
+        }
+    }
+
+    fn free(&self, mut index: usize, mut order: usize) {
+        while order + 1 < ORDERS {
+            // Claim the buddy if it is free, which prevents it from being allocated
+            if !self.order(order).unset(index ^ 1) {
+                break;
+            }
+
+            index >>= 1;
+            order += 1;
+        }
+
+        assert!(
+            !self.order(order).set(index),
+            "Double free of block {:#x} of order {}",
+            index << order,
+            order,
+        );
+    }
+
+    fn order(&self, order: usize) -> &bitset::Unsized {
+        let pointer = self.bits.load(Ordering::Acquire);
+        assert!(!pointer.is_null(), "Page allocator not initialized");
+
+        let pages = self.capacity();
+        let offset = Self::offset(pages, order);
+        let len = Self::offset(pages, order + 1) - offset;
+        unsafe { bitset::Unsized::from_raw_parts(pointer.add(offset), len) }
+    }
+
+    /// Offset in `u64`s of the bitset for `order`.
+    const fn offset(pages: usize, order: usize) -> usize {
+        let mut offset = 0;
+        let mut index = 0;
+        while index < order {
+            offset += pages.div_ceil(1 << index).div_ceil(64);
+            index += 1;
+        }
+        offset
+    }
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Dump {
+    free: [usize; ORDERS],
+    capacity: usize,
+}
+
+impl Debug for Dump {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let free = self
+            .free
+            .iter()
+            .enumerate()
+            .map(|(order, count)| count << order)
+            .sum::<usize>();
+
+        writeln!(f, "{:#x} / {:#x} pages free", free, self.capacity)?;
+        for (order, count) in self.free.iter().enumerate() {
+            writeln!(
+                f,
+                "  order {:>2} ({:#?}): {}",
+                order,
+                crate::unit::Byte::new((PAGE_SIZE as usize) << order),
+                count,
+            )?;
+        }
+        Ok(())
     }
 }
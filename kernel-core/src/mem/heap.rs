@@ -0,0 +1,264 @@
+use core::alloc::GlobalAlloc;
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use crate::mem::PAGE_SIZE;
+use crate::mem::Phys;
+use crate::mem::Virt;
+use crate::mem::alloc;
+use crate::mmu;
+use crate::sync::IrqSpinLock;
+
+#[global_allocator]
+static HEAP: Heap = Heap(IrqSpinLock::new(None));
+
+/// Start of the kernel heap's virtual address range.
+pub const BASE: u64 = crate::mem::OFFSET + crate::mem::LINEAR_SIZE;
+
+/// Maximum size of the kernel heap's virtual address range.
+pub const SIZE: u64 = 1 << 30;
+
+/// Allocations up to the largest size class are carved out of shared slab pages;
+/// anything larger is rounded up to whole pages.
+const CLASSES: [usize; 10] = [16, 32, 64, 128, 256, 512, 1024, 2048, 4096, 8192];
+
+pub struct Heap(IrqSpinLock<Option<Inner>>);
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Stats {
+    /// Bytes currently allocated, as requested by callers
+    pub allocated: usize,
+    /// Bytes of physical memory mapped into the heap
+    pub mapped: usize,
+    pub allocations: usize,
+    pub deallocations: usize,
+    pub failures: usize,
+}
+
+/// Requires the kernel page table to be initialized with [`mmu::init_kernel`].
+pub fn init() {
+    let mut heap = HEAP.0.lock();
+    assert!(heap.is_none(), "Heap already initialized");
+    *heap = Some(Inner {
+        next: BASE,
+        classes: [None; CLASSES.len()],
+        runs: None,
+        stats: Stats::default(),
+    });
+}
+
+pub fn stats() -> Stats {
+    HEAP.0
+        .lock()
+        .as_ref()
+        .map(|inner| inner.stats)
+        .unwrap_or_default()
+}
+
+unsafe impl GlobalAlloc for Heap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match self.0.lock().as_mut() {
+            None => core::ptr::null_mut(),
+            Some(inner) => inner.allocate(layout),
+        }
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        let pointer = NonNull::new(pointer).expect("Deallocating null pointer");
+        self.0
+            .lock()
+            .as_mut()
+            .expect("Deallocating before heap initialization")
+            .deallocate(pointer, layout)
+    }
+}
+
+struct Inner {
+    /// Start of the unmapped remainder of the heap's virtual address range
+    next: u64,
+
+    /// Free list per size class
+    classes: [Option<NonNull<Object>>; CLASSES.len()],
+
+    /// Free runs of whole pages, sorted by address
+    runs: Option<NonNull<Run>>,
+
+    stats: Stats,
+}
+
+// SAFETY: free list pointers refer to heap memory owned by `Inner`
+unsafe impl Send for Inner {}
+
+struct Object {
+    next: Option<NonNull<Object>>,
+}
+
+struct Run {
+    pages: usize,
+    next: Option<NonNull<Run>>,
+}
+
+impl Inner {
+    fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let pointer = match (Self::class(layout), layout.align() <= PAGE_SIZE as usize) {
+            (_, false) => None,
+            (Some(class), true) => self.allocate_object(class),
+            (None, true) => self.allocate_run(layout.size().div_ceil(PAGE_SIZE as usize)),
+        };
+
+        match pointer {
+            Some(pointer) => {
+                self.stats.allocations += 1;
+                self.stats.allocated += layout.size();
+                pointer.as_ptr()
+            }
+            None => {
+                self.stats.failures += 1;
+                warn!("Out of memory allocating {:?}: {:#x?}", layout, self.stats);
+                core::ptr::null_mut()
+            }
+        }
+    }
+
+    fn deallocate(&mut self, pointer: NonNull<u8>, layout: Layout) {
+        match Self::class(layout) {
+            Some(class) => {
+                let object = pointer.cast::<Object>();
+                unsafe {
+                    object.write(Object {
+                        next: self.classes[class],
+                    });
+                }
+                self.classes[class] = Some(object);
+            }
+            None => self.deallocate_run(pointer, layout.size().div_ceil(PAGE_SIZE as usize)),
+        }
+
+        self.stats.deallocations += 1;
+        self.stats.allocated -= layout.size();
+    }
+
+    fn class(layout: Layout) -> Option<usize> {
+        // Objects are naturally aligned within page-aligned slabs
+        let size = layout.size().max(layout.align());
+        CLASSES.iter().position(|class| size <= *class)
+    }
+
+    fn allocate_object(&mut self, class: usize) -> Option<NonNull<u8>> {
+        if self.classes[class].is_none() {
+            let slab = self.grow(1)?;
+
+            for offset in (0..PAGE_SIZE as usize).step_by(CLASSES[class]).rev() {
+                let object = unsafe { slab.byte_add(offset) }.cast::<Object>();
+                unsafe {
+                    object.write(Object {
+                        next: self.classes[class],
+                    });
+                }
+                self.classes[class] = Some(object);
+            }
+        }
+
+        let object = self.classes[class]?;
+        self.classes[class] = unsafe { object.as_ref().next };
+        Some(object.cast())
+    }
+
+    fn allocate_run(&mut self, pages: usize) -> Option<NonNull<u8>> {
+        let mut cursor = &mut self.runs;
+
+        // First fit
+        while let Some(mut run) = *cursor {
+            let run = unsafe { run.as_mut() };
+
+            if run.pages == pages {
+                *cursor = run.next;
+                return Some(NonNull::from(run).cast());
+            }
+
+            // Split off the tail so the list links stay intact
+            if run.pages > pages {
+                run.pages -= pages;
+                let tail =
+                    unsafe { NonNull::from(&mut *run).byte_add(run.pages * PAGE_SIZE as usize) };
+                return Some(tail.cast());
+            }
+
+            cursor = &mut run.next;
+        }
+
+        self.grow(pages)
+    }
+
+    fn deallocate_run(&mut self, pointer: NonNull<u8>, pages: usize) {
+        let address = pointer.as_ptr() as usize;
+        let end = |run: NonNull<Run>| {
+            run.as_ptr() as usize + unsafe { run.as_ref().pages } * PAGE_SIZE as usize
+        };
+
+        let mut prev: Option<NonNull<Run>> = None;
+        let mut next = self.runs;
+        while let Some(run) = next.filter(|run| (run.as_ptr() as usize) < address) {
+            prev = Some(run);
+            next = unsafe { run.as_ref().next };
+        }
+
+        // Merge with following run
+        let run = pointer.cast::<Run>();
+        unsafe {
+            match next {
+                Some(next) if address + pages * PAGE_SIZE as usize == next.as_ptr() as usize => run
+                    .write(Run {
+                        pages: pages + next.as_ref().pages,
+                        next: next.as_ref().next,
+                    }),
+                _ => run.write(Run { pages, next }),
+            }
+        }
+
+        // Merge with preceding run
+        match prev {
+            None => self.runs = Some(run),
+            Some(mut prev) if end(prev) == address => unsafe {
+                prev.as_mut().pages += run.as_ref().pages;
+                prev.as_mut().next = run.as_ref().next;
+            },
+            Some(mut prev) => unsafe { prev.as_mut().next = Some(run) },
+        }
+    }
+
+    /// Map `pages` fresh physical pages at the end of the heap.
+    fn grow(&mut self, pages: usize) -> Option<NonNull<u8>> {
+        let base = self.next;
+        let len = pages as u64 * PAGE_SIZE;
+
+        if base + len > BASE + SIZE {
+            return None;
+        }
+
+        for mapped in 0..pages {
+            let Some(page) = alloc::global().allocate() else {
+                // Keep any partially mapped pages around for later allocations
+                self.next += mapped as u64 * PAGE_SIZE;
+                if let Some(pointer) = NonNull::new(base as *mut u8).filter(|_| mapped > 0) {
+                    self.deallocate_run(pointer, mapped);
+                }
+                return None;
+            };
+
+            mmu::kernel(|page_table| {
+                page_table.map(
+                    alloc::global(),
+                    Virt::new(base + mapped as u64 * PAGE_SIZE),
+                    Phys::from(page),
+                    mmu::Attr::kernel(true, true, false),
+                )
+            });
+
+            self.stats.mapped += PAGE_SIZE as usize;
+        }
+
+        self.next += len;
+        NonNull::new(base as *mut u8)
+    }
+}
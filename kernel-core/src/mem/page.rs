@@ -1,3 +1,4 @@
+use crate::mem::PAGE_SHIFT;
 use crate::mem::Phys;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
@@ -21,6 +22,6 @@ impl From<Id> for u64 {
 
 impl From<Phys> for Id {
     fn from(phys: Phys) -> Self {
-        Self(u64::from(phys) >> 16)
+        Self(u64::from(phys) >> PAGE_SHIFT)
     }
 }
@@ -0,0 +1,136 @@
+use core::ops::Range;
+
+use crate::bitset;
+use crate::mem::Kernel;
+use crate::mem::PAGE_SIZE;
+use crate::mem::Phys;
+use crate::mem::Virt;
+use crate::mem::alloc;
+use crate::mem::heap;
+use crate::mmu;
+
+/// Bytes of memory backing each kernel stack.
+pub const SIZE: u64 = 1 << SHIFT;
+
+pub const SHIFT: u32 = 17;
+
+/// Start of the kernel stack virtual address range, directly after the heap.
+pub const BASE: u64 = heap::BASE + heap::SIZE;
+
+/// Each slot is an unmapped guard of `SIZE` bytes followed by the stack itself,
+/// aligned to the slot size. An address in a slot therefore lies within the
+/// stack (rather than its guard) exactly when bit `SHIFT` is set, which the
+/// exception vectors use to detect overflow without touching memory.
+const SLOT: u64 = SIZE * 2;
+
+/// Slots in the 1GiB stack region, which ends at 2^64, except the last: its
+/// top would not be representable.
+const SLOTS: usize = (1 << 30) / SLOT as usize - 1;
+
+/// Free slots are set.
+static FREE: bitset::Sized<{ SLOTS.div_ceil(64) }> = bitset::Sized::new();
+
+/// Kernel stack backed by pages from the global allocator, with an unmapped
+/// guard below it. Unmapped and freed on drop.
+pub struct Stack {
+    slot: usize,
+}
+
+pub(super) fn init() {
+    FREE.fill();
+    for slot in SLOTS..FREE.len() {
+        FREE.unset(slot);
+    }
+}
+
+impl Stack {
+    /// Allocate a stack backed by [`SIZE`] bytes. The stack must be fully
+    /// mapped: an unmapped page above the guard would pass the overflow check
+    /// in the exception vectors and fault again on every exception entry.
+    pub fn new() -> Option<Self> {
+        let slot = FREE.unset_first(0)?;
+        let stack = Stack { slot };
+        let top = u64::from(stack.top());
+
+        for offset in (PAGE_SIZE..=SIZE).step_by(PAGE_SIZE as usize) {
+            // Dropping `stack` unmaps any pages mapped so far
+            let page = alloc::global().allocate()?;
+            mmu::kernel(|page_table| {
+                page_table.map(
+                    alloc::global(),
+                    Virt::new(top - offset),
+                    Phys::from(page),
+                    mmu::Attr::kernel(true, true, false),
+                )
+            });
+        }
+
+        Some(stack)
+    }
+
+    /// Initial stack pointer, which the stack grows down from.
+    pub fn top(&self) -> Virt<Kernel> {
+        Virt::new(BASE + (self.slot as u64 + 1) * SLOT)
+    }
+
+    /// Lowest address of the stack, directly above its guard.
+    pub fn bottom(&self) -> Virt<Kernel> {
+        Virt::new(BASE + self.slot as u64 * SLOT + SIZE)
+    }
+
+    /// Give up ownership of this stack, returning its top.
+    pub fn leak(self) -> Virt<Kernel> {
+        let top = self.top();
+        core::mem::forget(self);
+        top
+    }
+
+    /// Switch to this stack and call `f`, leaking the stack.
+    pub fn enter(self, f: extern "C" fn() -> !) -> ! {
+        let top = u64::from(self.leak());
+
+        unsafe {
+            core::arch::asm!(
+                "mov sp, {top}",
+                "br {f}",
+                top = in(reg) top,
+                f = in(reg) f,
+                options(noreturn),
+            )
+        }
+    }
+}
+
+impl Drop for Stack {
+    fn drop(&mut self) {
+        let bottom = u64::from(self.bottom());
+
+        mmu::kernel(|page_table| {
+            for offset in (0..SIZE).step_by(PAGE_SIZE as usize) {
+                if let Some(phys) = page_table.unmap(alloc::global(), Virt::new(bottom + offset)) {
+                    alloc::global().deallocate(phys.into());
+                }
+            }
+        });
+
+        assert!(
+            !FREE.set(self.slot),
+            "Double free of stack slot {}",
+            self.slot
+        );
+    }
+}
+
+/// Revoke access to the guard below a statically allocated stack, such as the
+/// boot stack reserved by the linker script.
+pub fn guard(guard: Range<Virt<Kernel>>) {
+    assert_eq!(u64::from(guard.end) % SLOT, SIZE, "Misaligned stack guard");
+
+    mmu::kernel(|page_table| {
+        page_table.protect(
+            alloc::global(),
+            guard,
+            mmu::Attr::kernel(false, false, false),
+        )
+    });
+}
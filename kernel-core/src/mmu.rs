@@ -1,8 +1,13 @@
+use core::fmt::Debug;
 use core::marker::PhantomData;
 use core::ops::Deref;
-use core::ops::DerefMut;
+use core::ops::Range;
 
+use aarch64_cpu::asm::barrier;
+use aarch64_cpu::registers::ID_AA64MMFR0_EL1;
 use aarch64_cpu::registers::MAIR_EL1;
+use aarch64_cpu::registers::PAR_EL1;
+use aarch64_cpu::registers::ReadWriteable as _;
 use aarch64_cpu::registers::Readable as _;
 use aarch64_cpu::registers::TCR_EL1;
 use aarch64_cpu::registers::TTBR0_EL1;
@@ -11,28 +16,144 @@ use tock_registers::interfaces::Writeable;
 use tock_registers::register_bitfields;
 use tock_registers::registers::InMemoryRegister;
 
-#[repr(C)]
-pub struct PageTable<S> {
-    l3: [[Page; 8192]; 8],
-    l2: [Table; 8],
-    _space: PhantomData<S>,
+use crate::bitset;
+use crate::mem::AddressSpace;
+use crate::mem::Kernel;
+use crate::mem::Phys;
+use crate::mem::User;
+use crate::mem::Virt;
+use crate::sync::IrqSpinLock;
+
+/// Translation granule, which determines the page size and the number of
+/// address bits resolved by each level of the page table.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Granule {
+    KiB4,
+    KiB64,
+}
+
+impl Granule {
+    pub const fn shift(&self) -> u32 {
+        match self {
+            Granule::KiB4 => 12,
+            Granule::KiB64 => 16,
+        }
+    }
+
+    pub const fn size(&self) -> u64 {
+        1 << self.shift()
+    }
+
+    /// Bits of virtual address resolved by each level, given 8-byte descriptors.
+    const fn stride(&self) -> u32 {
+        self.shift() - 3
+    }
+
+    /// Bytes mapped by a single entry at `level`.
+    pub const fn size_at(&self, level: u32) -> u64 {
+        1 << (self.shift() + self.stride() * (3 - level))
+    }
+
+    /// Whether block descriptors are permitted at `level` (without FEAT_LPA).
+    pub const fn has_block(&self, level: u32) -> bool {
+        matches!((self, level), (Granule::KiB4, 1 | 2) | (Granule::KiB64, 2))
+    }
+}
+
+/// Translation regime shared by the kernel (TTBR1) and user (TTBR0) page tables.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub granule: Granule,
+    /// Size of the user address space in bits (64 - T0SZ)
+    pub user_bits: u32,
+    /// Size of the kernel address space in bits (64 - T1SZ)
+    pub kernel_bits: u32,
 }
 
-pub fn init() {
+impl Config {
+    pub const DEFAULT: Self = Self {
+        granule: Granule::KiB64,
+        user_bits: 48,
+        kernel_bits: 48,
+    };
+
+    /// Recover the configuration programmed into `TCR_EL1` by [`init`].
+    ///
+    /// # Panics
+    ///
+    /// If `TCR_EL1` has not been programmed by [`init`], which only ever
+    /// selects a supported granule.
+    pub fn current() -> Self {
+        let granule = match TCR_EL1.read_as_enum(TCR_EL1::TG1) {
+            Some(TCR_EL1::TG1::Value::KiB_4) => Granule::KiB4,
+            Some(TCR_EL1::TG1::Value::KiB_64) => Granule::KiB64,
+            _ => unreachable!("Translation granule not programmed by mmu::init"),
+        };
+
+        Self {
+            granule,
+            user_bits: 64 - TCR_EL1.read(TCR_EL1::T0SZ) as u32,
+            kernel_bits: 64 - TCR_EL1.read(TCR_EL1::T1SZ) as u32,
+        }
+    }
+
+    fn bits<S: AddressSpace>(&self) -> u32 {
+        match S::KERNEL {
+            true => self.kernel_bits,
+            false => self.user_bits,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+pub fn init(config: &Config) {
+    for bits in [config.user_bits, config.kernel_bits] {
+        assert!(
+            (25..=48).contains(&bits),
+            "Unsupported address space size: {bits} bits",
+        );
+    }
+
+    // Indices must match `Attr::index`
     MAIR_EL1.write(
         MAIR_EL1::Attr0_Device::nonGathering_nonReordering_noEarlyWriteAck
             + MAIR_EL1::Attr1_Normal_Outer::WriteBack_NonTransient_ReadWriteAlloc
-            + MAIR_EL1::Attr1_Normal_Inner::WriteBack_NonTransient_ReadWriteAlloc,
+            + MAIR_EL1::Attr1_Normal_Inner::WriteBack_NonTransient_ReadWriteAlloc
+            + MAIR_EL1::Attr2_Device::nonGathering_nonReordering_EarlyWriteAck
+            + MAIR_EL1::Attr3_Normal_Outer::NonCacheable
+            + MAIR_EL1::Attr3_Normal_Inner::NonCacheable
+            + MAIR_EL1::Attr4_Normal_Outer::WriteThrough_NonTransient_ReadAlloc
+            + MAIR_EL1::Attr4_Normal_Inner::WriteThrough_NonTransient_ReadAlloc,
     );
 
+    let (tg0, tg1) = match config.granule {
+        Granule::KiB4 => (TCR_EL1::TG0::KiB_4, TCR_EL1::TG1::KiB_4),
+        Granule::KiB64 => (TCR_EL1::TG0::KiB_64, TCR_EL1::TG1::KiB_64),
+    };
+
+    // Output addresses are limited to what the CPU implements
+    let ips = match ID_AA64MMFR0_EL1.read_as_enum(ID_AA64MMFR0_EL1::PARange) {
+        Some(ID_AA64MMFR0_EL1::PARange::Value::Bits_32) => TCR_EL1::IPS::Bits_32,
+        Some(ID_AA64MMFR0_EL1::PARange::Value::Bits_36) => TCR_EL1::IPS::Bits_36,
+        Some(ID_AA64MMFR0_EL1::PARange::Value::Bits_40) => TCR_EL1::IPS::Bits_40,
+        Some(ID_AA64MMFR0_EL1::PARange::Value::Bits_42) => TCR_EL1::IPS::Bits_42,
+        Some(ID_AA64MMFR0_EL1::PARange::Value::Bits_44) => TCR_EL1::IPS::Bits_44,
+        _ => TCR_EL1::IPS::Bits_48,
+    };
+
     TCR_EL1.write(
         // TCR_EL1::HD::Enable
         // TCR_EL1::HA::Enable
         TCR_EL1::TBI0::Used
             + TCR_EL1::TBI1::Used
             + TCR_EL1::A1::TTBR0
-            + TCR_EL1::TG0::KiB_64
-            + TCR_EL1::TG1::KiB_64
+            + tg0
+            + tg1
             + TCR_EL1::SH0::Inner
             + TCR_EL1::SH1::Inner
             + TCR_EL1::ORGN0::WriteBack_ReadAlloc_WriteAlloc_Cacheable
@@ -41,108 +162,898 @@ pub fn init() {
             + TCR_EL1::IRGN1::WriteBack_ReadAlloc_WriteAlloc_Cacheable
             + TCR_EL1::EPD0::EnableTTBR0Walks
             + TCR_EL1::EPD1::EnableTTBR1Walks
-            + TCR_EL1::IPS::Bits_32
+            + ips
             + TCR_EL1::AS::ASID8Bits
-            + TCR_EL1::T0SZ.val(32)
-            + TCR_EL1::T1SZ.val(32),
+            + TCR_EL1::T0SZ.val(64 - config.user_bits as u64)
+            + TCR_EL1::T1SZ.val(64 - config.kernel_bits as u64),
+    );
+
+    assert_eq!(
+        Config::current(),
+        *config,
+        "TCR_EL1 does not match configuration"
     );
 }
 
-impl<S: crate::mem::AddressSpace> PageTable<S> {
-    pub fn init(&mut self, offset: u64) {
-        // FIXME: set up device MMIO in kernel
-        for (virt, phys) in (0x3F00_0000..0x4001_0000)
-            .step_by(1 << 16)
-            .map(|phys| (phys + offset, phys))
-            .map(|(virt, phys)| (crate::mem::Virt::new(virt), crate::mem::Phys::new(phys)))
-        {
-            self.map(virt, phys, Attr::Device);
-        }
-
-        match offset {
-            0 => TTBR0_EL1.set_baddr(self.l2.as_ptr() as u64),
-            _ => {
-                // Make page table itself accessible
-                for (virt, phys) in (self as *mut _ as u64..)
-                    .step_by(1 << 16)
-                    .take(self.l3.len() + 1)
-                    .map(|phys| (phys + offset, phys))
-                    .map(|(virt, phys)| (crate::mem::Virt::new(virt), crate::mem::Phys::new(phys)))
-                {
-                    self.map(
-                        virt,
-                        phys,
-                        Attr::Normal {
-                            read: true,
-                            write: true,
-                            execute: false,
-                        },
-                    );
+/// Source of physical frames for page table levels.
+///
+/// Each frame must be aligned to and at least as large as the translation
+/// granule. Frames need not be zeroed.
+pub trait Allocator {
+    fn allocate(&self) -> Option<Phys>;
+    fn deallocate(&self, frame: Phys);
+}
+
+impl Allocator for crate::mem::alloc::Page {
+    fn allocate(&self) -> Option<Phys> {
+        self.allocate().map(Phys::from)
+    }
+
+    fn deallocate(&self, frame: Phys) {
+        self.deallocate(frame.into())
+    }
+}
+
+static KERNEL: IrqSpinLock<Option<PageTable<Kernel>>> = IrqSpinLock::new(None);
+
+/// Take ownership of the kernel page table currently installed in `TTBR1_EL1`.
+///
+/// # Safety
+///
+/// Caller must guarantee that the installed kernel page table contains the
+/// linear map (see [`PageTable::map_linear`]) of every page table frame.
+pub unsafe fn init_kernel() {
+    let mut kernel = KERNEL.lock();
+    assert!(kernel.is_none(), "Kernel page table already initialized");
+    *kernel = Some(unsafe {
+        PageTable::from_raw(
+            Phys::new(TTBR1_EL1.get_baddr()),
+            Config::current(),
+            crate::mem::OFFSET,
+        )
+    });
+}
+
+/// Run `f` with exclusive access to the kernel page table.
+pub fn kernel<T>(f: impl FnOnce(&mut PageTable<Kernel>) -> T) -> T {
+    f(KERNEL
+        .lock()
+        .as_mut()
+        .expect("Kernel page table not initialized"))
+}
+
+/// Translate `virt` through the tables installed on this core with the
+/// hardware walker, which needs neither [`init_kernel`] nor the linear map.
+pub fn translate(virt: Virt<Kernel>) -> Option<Phys> {
+    let virt = u64::from(virt);
+    unsafe { core::arch::asm!("at s1e1r, {virt}", virt = in(reg) virt, options(nostack)) };
+    barrier::isb(barrier::SY);
+
+    match PAR_EL1.is_set(PAR_EL1::F) {
+        true => None,
+        false => Some(Phys::new(
+            (PAR_EL1.read(PAR_EL1::PA) << 12) | (virt & 0xFFF),
+        )),
+    }
+}
+
+/// Remove the bootstrap identity map installed in `TTBR0_EL1` by the
+/// bootloader, returning its table frames to `allocator`.
+///
+/// # Safety
+///
+/// Caller must guarantee that nothing is accessed through the identity map
+/// afterward, and that its table frames are covered by the linear map.
+pub unsafe fn unmap_identity(allocator: &impl Allocator) {
+    let root = TTBR0_EL1.get_baddr();
+    if root == 0 {
+        return;
+    }
+
+    uninstall_user();
+
+    unsafe { PageTable::<User>::from_raw(Phys::new(root), Config::current(), crate::mem::OFFSET) }
+        .deallocate(allocator);
+}
+
+/// Stop translating through `TTBR0_EL1` on this core, so that user
+/// addresses fault until a user page table is installed.
+pub(crate) fn uninstall_user() {
+    TCR_EL1.modify(TCR_EL1::EPD0::DisableTTBR0Walks);
+    TTBR0_EL1.set_baddr(0);
+    barrier::isb(barrier::SY);
+
+    unsafe {
+        core::arch::asm!("tlbi vmalle1", options(nostack));
+    }
+    barrier::dsb(barrier::NSH);
+    barrier::isb(barrier::SY);
+}
+
+/// Translate user addresses on this core through the table at `root`, whose
+/// TLB entries are tagged with `asid`.
+pub fn activate_user(root: Phys, asid: &Asid) {
+    TTBR0_EL1
+        .write(TTBR0_EL1::ASID.val(asid.0 as u64) + TTBR0_EL1::BADDR.val(u64::from(root) >> 1));
+    TCR_EL1.modify(TCR_EL1::EPD0::EnableTTBR0Walks);
+    barrier::isb(barrier::SY);
+}
+
+/// Stop translating user addresses on this core, e.g. while running a
+/// kernel thread. Cached entries remain tagged with their ASID, so no TLB
+/// maintenance is needed.
+pub fn deactivate_user() {
+    TCR_EL1.modify(TCR_EL1::EPD0::DisableTTBR0Walks);
+    barrier::isb(barrier::SY);
+}
+
+/// Address space identifiers in use, with ASID 0 reserved for translations
+/// that belong to no process (such as the boot identity map).
+static ASIDS: bitset::Sized<4> = bitset::Sized::new();
+
+/// Address space identifier, which tags the non-global TLB entries of a user
+/// page table so that switching tables requires no TLB invalidation.
+///
+/// `TCR_EL1.AS` selects 8-bit ASIDs. Entries tagged with the ASID are
+/// invalidated on every core when it is dropped.
+#[derive(Debug)]
+pub struct Asid(u8);
+
+impl Asid {
+    pub fn allocate() -> Option<Self> {
+        (1..256)
+            .find(|asid| !ASIDS.set(*asid))
+            .map(|asid| Self(asid as u8))
+    }
+
+    pub fn value(&self) -> u8 {
+        self.0
+    }
+
+    /// Invalidate entries tagged with this ASID on every core, e.g. before
+    /// freeing frames that they may still point to.
+    pub fn invalidate(&self) {
+        barrier::dsb(barrier::ISHST);
+        unsafe {
+            core::arch::asm!(
+                "tlbi aside1is, {asid}",
+                asid = in(reg) (self.0 as u64) << 48,
+                options(nostack),
+            );
+        }
+        barrier::dsb(barrier::ISH);
+        barrier::isb(barrier::SY);
+    }
+}
+
+impl Drop for Asid {
+    fn drop(&mut self) {
+        self.invalidate();
+        ASIDS.unset(self.0 as usize);
+    }
+}
+
+/// Report kernel mappings that are both writable and executable, which
+/// `SCTLR_EL1.WXN` makes non-executable. Returns the number of violations.
+pub fn check_wx() -> usize {
+    kernel(|page_table| {
+        page_table
+            .iter()
+            .filter(|mapping| mapping.attr.is_writable_executable())
+            .inspect(|mapping| {
+                warn!(
+                    "W^X violation: {:#x} - {:#x} -> {:#x} {:?}",
+                    u64::from(mapping.virt),
+                    u64::from(mapping.virt).wrapping_add(mapping.len),
+                    u64::from(mapping.phys),
+                    mapping.attr,
+                )
+            })
+            .count()
+    })
+}
+
+impl PageTable<Kernel> {
+    /// Map each range of RAM at its linear address (see [`Phys::to_virt`]),
+    /// using block mappings where alignment permits. Ranges are rounded
+    /// inward to the granule.
+    pub fn map_linear<M>(&mut self, allocator: &impl Allocator, memory: M)
+    where
+        M: IntoIterator<Item = Range<Phys>>,
+    {
+        let granule = self.config.granule.size();
+
+        for range in memory {
+            let start = u64::from(range.start).next_multiple_of(granule);
+            let end = u64::from(range.end) & !(granule - 1);
+            if start >= end {
+                continue;
+            }
+
+            self.map_range(
+                allocator,
+                Phys::new(start).to_virt(),
+                Phys::new(start),
+                end - start,
+                Attr::kernel(true, true, false),
+            );
+        }
+    }
+}
+
+/// Multi-level translation table whose levels are allocated on demand.
+///
+/// Table frames are addressed physically in descriptors, and accessed by the
+/// CPU at their physical address plus `window`: zero with the MMU off, or the
+/// offset of the linear map once it is installed.
+pub struct PageTable<S> {
+    root: Phys,
+    config: Config,
+    window: u64,
+    _space: PhantomData<S>,
+}
+
+impl<S: AddressSpace> PageTable<S> {
+    pub fn new(config: Config, window: u64, allocator: &impl Allocator) -> Option<Self> {
+        let root = allocator.allocate()?;
+        let table = Self {
+            root,
+            config,
+            window,
+            _space: PhantomData,
+        };
+        table.zero(root);
+        Some(table)
+    }
+
+    /// # Safety
+    ///
+    /// Caller must guarantee `root` is a page table built with `config`,
+    /// whose frames are accessible at their physical address plus `window`.
+    pub unsafe fn from_raw(root: Phys, config: Config, window: u64) -> Self {
+        Self {
+            root,
+            config,
+            window,
+            _space: PhantomData,
+        }
+    }
+
+    pub fn root(&self) -> Phys {
+        self.root
+    }
+
+    pub fn config(&self) -> Config {
+        self.config
+    }
+
+    /// Change where table frames are accessed, e.g. after enabling the MMU.
+    pub fn set_window(&mut self, window: u64) {
+        self.window = window;
+    }
+
+    /// Point the translation table base register for this address space at this table.
+    pub fn install(&self) {
+        match S::KERNEL {
+            true => TTBR1_EL1.set_baddr(u64::from(self.root)),
+            false => TTBR0_EL1.set_baddr(u64::from(self.root)),
+        }
+        barrier::isb(barrier::SY);
+    }
+
+    /// Map a single page.
+    pub fn map(&mut self, allocator: &impl Allocator, virt: Virt<S>, phys: Phys, attr: Attr) {
+        self.map_range(allocator, virt, phys, self.config.granule.size(), attr)
+    }
+
+    /// Map `len` bytes starting at `virt` to `phys`, using the largest block
+    /// mappings permitted by alignment. Existing mappings are replaced, and
+    /// blocks are split as needed to map part of them.
+    pub fn map_range(
+        &mut self,
+        allocator: &impl Allocator,
+        virt: Virt<S>,
+        phys: Phys,
+        len: u64,
+        attr: Attr,
+    ) {
+        let virt = u64::from(virt);
+        let phys = u64::from(phys);
+        let granule = self.config.granule.size();
+
+        assert_eq!(virt % granule, 0, "Misaligned virtual address {virt:#x}");
+        assert_eq!(phys % granule, 0, "Misaligned physical address {phys:#x}");
+        assert_eq!(len % granule, 0, "Misaligned length {len:#x}");
+        assert!(
+            self.contains(virt, len),
+            "Out of range: {virt:#x} + {len:#x}"
+        );
+
+        let mut offset = 0;
+        while offset < len {
+            offset += self.map_one(allocator, virt + offset, phys + offset, len - offset, attr);
+        }
+
+        barrier::dsb(barrier::ISHST);
+        barrier::isb(barrier::SY);
+    }
+
+    /// Remove the page mapped at `virt`, returning its physical address.
+    ///
+    /// Blocks are split as needed to unmap part of them.
+    pub fn unmap(&mut self, allocator: &impl Allocator, virt: Virt<S>) -> Option<Phys> {
+        let virt = u64::from(virt);
+        let len = self.config.granule.size();
+        assert_eq!(virt % len, 0, "Misaligned virtual address {virt:#x}");
+
+        let phys = self.translate_raw(virt).map(|(phys, _)| phys);
+
+        let mut offset = 0;
+        while offset < len {
+            let (entry, size) = self.leaf_within(allocator, virt + offset, len - offset);
+            if let Some((entry, _)) = entry {
+                entry.set(0);
+                Self::invalidate(virt + offset);
+            }
+            offset += size;
+        }
+
+        barrier::isb(barrier::SY);
+        phys
+    }
+
+    /// Change the attributes of every mapping in `range`, leaving unmapped
+    /// addresses untouched. Blocks are split as needed to protect part of them.
+    pub fn protect(&mut self, allocator: &impl Allocator, range: Range<Virt<S>>, attr: Attr) {
+        let virt = u64::from(range.start);
+        let len = u64::from(range.end) - virt;
+        let granule = self.config.granule.size();
+
+        assert_eq!(virt % granule, 0, "Misaligned virtual address {virt:#x}");
+        assert_eq!(len % granule, 0, "Misaligned length {len:#x}");
+
+        let mut offset = 0;
+        while offset < len {
+            let (entry, size) = self.leaf_within(allocator, virt + offset, len - offset);
+            if let Some((entry, level)) = entry {
+                let phys = Phys::new(entry.read(page::NEXT) << 12);
+                entry.set(Self::leaf(phys, attr, level));
+                Self::invalidate(virt + offset);
+            }
+            offset += size;
+        }
+
+        barrier::isb(barrier::SY);
+    }
+
+    /// Look up the physical address and attributes that `virt` maps to.
+    pub fn translate(&self, virt: Virt<S>) -> Option<(Phys, Attr)> {
+        self.translate_raw(u64::from(virt))
+    }
+
+    /// Iterate over leaf mappings (blocks and pages) in address order.
+    pub fn iter(&self) -> Iter<'_, S> {
+        Iter {
+            table: self,
+            offset: 0,
+        }
+    }
+
+    /// Deallocate every table frame, leaving the frames they map untouched.
+    ///
+    /// The table must not be installed.
+    pub fn deallocate(self, allocator: &impl Allocator) {
+        self.deallocate_table(allocator, self.root, self.root_level());
+    }
+
+    fn deallocate_table(&self, allocator: &impl Allocator, table: Phys, level: u32) {
+        if level < 3 {
+            for index in 0..self.entries() {
+                let entry = unsafe { &*self.frame(table).add(index as usize) };
+                if Self::is_table(entry) {
+                    let next = Phys::new(entry.read(page::NEXT) << 12);
+                    self.deallocate_table(allocator, next, level + 1);
                 }
+            }
+        }
 
-                TTBR1_EL1.set_baddr(self.l2.as_ptr() as u64);
+        allocator.deallocate(table);
+    }
+
+    fn translate_raw(&self, virt: u64) -> Option<(Phys, Attr)> {
+        match self.walk(virt) {
+            Walk::Leaf(entry, level) => {
+                let size = self.config.granule.size_at(level);
+                let phys = (entry.read(page::NEXT) << 12) + (virt & (size - 1));
+                Some((Phys::new(phys), Self::attr(entry)))
             }
+            Walk::Invalid(_) => None,
         }
     }
 
-    pub fn map(&mut self, virt: crate::mem::Virt<S>, phys: crate::mem::Phys, attr: Attr) {
-        let index_l2 = (u64::from(virt) >> 29 & ((1 << 3) - 1)) as usize;
-        let index_l3 = ((u64::from(virt) >> 16) & ((1 << 13) - 1)) as usize;
+    /// Find the entry mapping `virt` without modifying the table.
+    fn walk(&self, virt: u64) -> Walk<'_> {
+        let mut table = self.root;
+        let mut level = self.root_level();
 
-        let table = &mut self.l2[index_l2];
-        if !table.is_set(table::VALID) {
-            table.write(
-                table::TYPE::Table
-                    + table::VALID::Valid
-                    + table::NEXT.val((self.l3[index_l2].as_ptr() as u64) >> 16),
-            )
+        loop {
+            let entry = self.entry(table, level, virt);
+
+            if !entry.is_set(page::VALID) {
+                return Walk::Invalid(level);
+            }
+
+            if level == 3 || !Self::is_table(entry) {
+                return Walk::Leaf(entry, level);
+            }
+
+            table = Phys::new(entry.read(page::NEXT) << 12);
+            level += 1;
+        }
+    }
+
+    /// Find the leaf entry mapping `virt`, splitting blocks that extend
+    /// outside of `virt..virt + len`. Returns the entry and its level if
+    /// valid, and the number of bytes it covers (or would cover) from `virt`.
+    fn leaf_within(
+        &self,
+        allocator: &impl Allocator,
+        virt: u64,
+        len: u64,
+    ) -> (Option<(&Page, u32)>, u64) {
+        let mut table = self.root;
+        let mut level = self.root_level();
+
+        loop {
+            let size = self.config.granule.size_at(level);
+            let entry = self.entry(table, level, virt);
+
+            if !entry.is_set(page::VALID) {
+                return (None, size - (virt & (size - 1)));
+            }
+
+            if level < 3 && Self::is_table(entry) {
+                table = Phys::new(entry.read(page::NEXT) << 12);
+            } else if level == 3 || (virt % size == 0 && len >= size) {
+                return (Some((entry, level)), size);
+            } else {
+                table = self.split(allocator, entry, level, virt);
+            }
+
+            level += 1;
+        }
+    }
+
+    /// Map the largest block or page that fits at `virt`, returning its size.
+    fn map_one(
+        &mut self,
+        allocator: &impl Allocator,
+        virt: u64,
+        phys: u64,
+        len: u64,
+        attr: Attr,
+    ) -> u64 {
+        let granule = self.config.granule;
+        let mut table = self.root;
+        let mut level = self.root_level();
+
+        loop {
+            let size = granule.size_at(level);
+            let entry = self.entry(table, level, virt);
+            let table_entry = level < 3 && Self::is_table(entry);
+
+            if !table_entry
+                && (level == 3
+                    || (granule.has_block(level)
+                        && virt % size == 0
+                        && phys % size == 0
+                        && len >= size))
+            {
+                // Break-before-make when replacing a live mapping
+                if entry.is_set(page::VALID) {
+                    entry.set(0);
+                    Self::invalidate(virt);
+                }
+                entry.set(Self::leaf(Phys::new(phys), attr, level));
+                return size;
+            }
+
+            table = match (entry.is_set(page::VALID), table_entry) {
+                (true, true) => Phys::new(entry.read(page::NEXT) << 12),
+                (true, false) => self.split(allocator, entry, level, virt),
+                (false, _) => {
+                    let next = allocator.allocate().expect("Out of memory for page table");
+                    self.zero(next);
+                    self.link(entry, next);
+                    next
+                }
+            };
+
+            level += 1;
+        }
+    }
+
+    /// Replace the block mapped by `entry` with a table of equivalent
+    /// mappings one level down.
+    ///
+    /// Uses break-before-make, so the block's range must not be accessed
+    /// (e.g. by the caller's own code or stack) while it is being split.
+    fn split(&self, allocator: &impl Allocator, entry: &Page, level: u32, virt: u64) -> Phys {
+        let next = allocator.allocate().expect("Out of memory for page table");
+        let block = entry.get();
+        let address = entry.read(page::NEXT) << 12;
+        let size = self.config.granule.size_at(level + 1);
+
+        let attributes =
+            block & !(page::NEXT.mask << page::NEXT.shift) & !(page::TYPE.mask << page::TYPE.shift);
+        let kind = match level + 1 {
+            3 => page::TYPE::Page.value,
+            _ => page::TYPE::Block.value,
+        };
+
+        for index in 0..self.entries() {
+            let child = unsafe { &*self.frame(next).add(index as usize) };
+            child.set(attributes | kind | page::NEXT.val((address + index * size) >> 12).value);
         }
 
-        let page = &mut self.l3[index_l2][index_l3];
-        let mut flags = page::AF::True
-            + page::TYPE::Page
-            + page::VALID::True
-            + page::NEXT.val(u64::from(phys) >> 16);
+        entry.set(0);
+        Self::invalidate(virt);
+        self.link(entry, next);
+        next
+    }
+
+    /// Invalidate cached translations of `virt` under any ASID on every core in
+    /// the inner shareable domain, once preceding table writes are visible to
+    /// the walker.
+    fn invalidate(virt: u64) {
+        barrier::dsb(barrier::ISHST);
+        unsafe {
+            core::arch::asm!(
+                "tlbi vaae1is, {page}",
+                page = in(reg) (virt >> 12) & ((1 << 44) - 1),
+                options(nostack),
+            );
+        }
+        barrier::dsb(barrier::ISH);
+    }
+
+    fn attr(entry: &Page) -> Attr {
+        let index = entry.read(page::INDEX);
+        let cache = match index {
+            Attr::DEVICE => return Attr::Device,
+            Attr::DEVICE_EARLY_ACK => return Attr::DeviceEarlyAck,
+            Attr::WRITE_BACK => Cache::WriteBack,
+            Attr::NON_CACHEABLE => Cache::NonCacheable,
+            Attr::WRITE_THROUGH => Cache::WriteThrough,
+            _ => unreachable!("Unknown memory attribute index: {index}"),
+        };
+
+        let ap = entry.read_as_enum(page::AP);
+        Attr::Normal {
+            read: entry.is_set(page::AF),
+            write: entry.is_set(page::AF)
+                && matches!(ap, Some(page::AP::Value::RW_EL1 | page::AP::Value::RW_EL0)),
+            execute: !entry.is_set(page::PXN),
+            user: matches!(ap, Some(page::AP::Value::RW_EL0 | page::AP::Value::RO_EL0)),
+            user_execute: !entry.is_set(page::UXN),
+            cache,
+            global: !entry.is_set(page::NG),
+        }
+    }
+
+    fn link(&self, entry: &Page, next: Phys) {
+        let table = unsafe { &*(entry as *const Page as *const Table) };
+        table.write(
+            table::TYPE::Table + table::VALID::Valid + table::NEXT.val(u64::from(next) >> 12),
+        );
+        barrier::dsb(barrier::ISHST);
+    }
+
+    fn leaf(phys: Phys, attr: Attr, level: u32) -> u64 {
+        let mut flags = page::VALID::True + page::NEXT.val(u64::from(phys) >> 12);
+
+        flags += match level {
+            3 => page::TYPE::Page,
+            _ => page::TYPE::Block,
+        };
+
+        flags += page::INDEX.val(attr.index());
 
         match attr {
-            Attr::Device => flags += page::SH::Outer + page::AP::RW_EL1 + page::INDEX.val(0),
+            Attr::Device | Attr::DeviceEarlyAck => {
+                flags += page::AF::True
+                    + page::SH::Outer
+                    + page::AP::RW_EL1
+                    + page::PXN::SET
+                    + page::UXN::SET;
+            }
             Attr::Normal {
                 read,
                 write,
                 execute,
+                user,
+                user_execute,
+                cache: _,
+                global,
             } => {
-                flags += page::SH::Inner + page::INDEX.val(1);
+                // Leave the access flag clear on inaccessible mappings so any
+                // access faults, while keeping the translation queryable
+                flags += match read || write {
+                    true => page::AF::True,
+                    false => page::AF::False,
+                };
 
-                if execute {
-                    flags += page::PXN::CLEAR;
-                } else {
-                    flags += page::PXN::SET;
-                }
+                flags += page::SH::Inner;
+
+                flags += match (write, user) {
+                    (true, false) => page::AP::RW_EL1,
+                    (false, false) => page::AP::RO_EL1,
+                    (true, true) => page::AP::RW_EL0,
+                    (false, true) => page::AP::RO_EL0,
+                };
 
-                flags += page::UXN::SET;
+                flags += match execute {
+                    true => page::PXN::CLEAR,
+                    false => page::PXN::SET,
+                };
+
+                flags += match user_execute {
+                    true => page::UXN::CLEAR,
+                    false => page::UXN::SET,
+                };
 
-                flags += match (read, write) {
-                    (true, true) => page::AP::RW_EL1,
-                    (true, false) => page::AP::RO_EL1,
-                    _ => unimplemented!(),
+                flags += match global {
+                    true => page::NG::CLEAR,
+                    false => page::NG::SET,
                 };
             }
         }
 
-        page.write(flags);
+        flags.value
+    }
+
+    fn is_table(entry: &Page) -> bool {
+        let table = unsafe { &*(entry as *const Page as *const Table) };
+        table.matches_all(table::VALID::Valid + table::TYPE::Table)
+    }
+
+    fn entry(&self, table: Phys, level: u32, virt: u64) -> &Page {
+        let granule = self.config.granule;
+        let bits = self.config.bits::<S>();
+        let index = (virt & ((1 << bits) - 1)) >> (granule.size_at(level).trailing_zeros());
+        let index = index & (self.entries() - 1);
+        unsafe { &*self.frame(table).add(index as usize) }
+    }
+
+    fn entries(&self) -> u64 {
+        1 << self.config.granule.stride()
+    }
+
+    fn frame(&self, table: Phys) -> *mut Page {
+        core::ptr::with_exposed_provenance_mut(u64::from(table).wrapping_add(self.window) as usize)
+    }
+
+    fn zero(&self, table: Phys) {
+        unsafe {
+            core::ptr::write_bytes(
+                self.frame(table).cast::<u8>(),
+                0,
+                self.config.granule.size() as usize,
+            )
+        }
+    }
+
+    fn root_level(&self) -> u32 {
+        let granule = self.config.granule;
+        let levels = (self.config.bits::<S>() - granule.shift()).div_ceil(granule.stride());
+        4 - levels
+    }
+
+    fn contains(&self, virt: u64, len: u64) -> bool {
+        let bits = self.config.bits::<S>();
+        match S::KERNEL {
+            true => virt >= u64::MAX << bits && len <= virt.wrapping_neg(),
+            false => virt <= 1 << bits && len <= (1 << bits) - virt,
+        }
     }
 }
 
+enum Walk<'a> {
+    Leaf(&'a Page, u32),
+    Invalid(u32),
+}
+
+/// Contiguous range of virtual memory mapped by a single block or page.
 #[derive(Copy, Clone, Debug)]
+pub struct Mapping<S> {
+    pub virt: Virt<S>,
+    pub phys: Phys,
+    pub len: u64,
+    pub attr: Attr,
+}
+
+pub struct Iter<'a, S> {
+    table: &'a PageTable<S>,
+    /// Offset of the next address to visit from the start of the address space
+    offset: u64,
+}
+
+impl<S: AddressSpace> Iterator for Iter<'_, S> {
+    type Item = Mapping<S>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bits = self.table.config.bits::<S>();
+        let base = match S::KERNEL {
+            true => u64::MAX << bits,
+            false => 0,
+        };
+
+        while self.offset < 1 << bits {
+            let virt = base + self.offset;
+            match self.table.walk(virt) {
+                Walk::Invalid(level) => {
+                    let size = self.table.config.granule.size_at(level);
+                    self.offset += size - (self.offset & (size - 1));
+                }
+                Walk::Leaf(entry, level) => {
+                    let len = self.table.config.granule.size_at(level);
+                    self.offset += len;
+                    return Some(Mapping {
+                        virt: Virt::new(virt),
+                        phys: Phys::new(entry.read(page::NEXT) << 12),
+                        len,
+                        attr: PageTable::<S>::attr(entry),
+                    });
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl<S: AddressSpace> Debug for PageTable<S> {
+    /// Lists mappings, merging neighbors that are physically contiguous with
+    /// identical attributes.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        fn line<S: AddressSpace>(
+            f: &mut core::fmt::Formatter<'_>,
+            run: &Mapping<S>,
+        ) -> core::fmt::Result {
+            let virt = u64::from(run.virt);
+            writeln!(
+                f,
+                "  {:#018x} - {:#018x} -> {:#x} ({:#?}) {:?}",
+                virt,
+                virt.wrapping_add(run.len),
+                u64::from(run.phys),
+                crate::unit::Byte::new(run.len as usize),
+                run.attr,
+            )
+        }
+
+        writeln!(f, "PageTable @ {:?} ({:?})", self.root, self.config)?;
+
+        let mut run: Option<Mapping<S>> = None;
+        for mapping in self.iter() {
+            match &mut run {
+                Some(run)
+                    if u64::from(run.virt).wrapping_add(run.len) == u64::from(mapping.virt)
+                        && u64::from(run.phys) + run.len == u64::from(mapping.phys)
+                        && run.attr == mapping.attr =>
+                {
+                    run.len += mapping.len;
+                }
+                _ => {
+                    if let Some(run) = &run {
+                        line(f, run)?;
+                    }
+                    run = Some(mapping);
+                }
+            }
+        }
+
+        match &run {
+            Some(run) => line(f, run),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Attr {
+    /// Device-nGnRnE: no gathering, reordering, or early write acknowledgement
     Device,
+    /// Device-nGnRE: allows the interconnect to acknowledge writes early
+    DeviceEarlyAck,
     Normal {
+        /// Readable at EL1, or if `user`, at EL0 as well
         read: bool,
+        /// Writable at EL1, or if `user`, at EL0 as well. There is no
+        /// write-only encoding, so this implies `read`.
         write: bool,
+        /// Executable at EL1
         execute: bool,
+        /// Accessible at EL0 with the same `read` and `write` permissions
+        user: bool,
+        /// Executable at EL0
+        user_execute: bool,
+        cache: Cache,
+        /// Matches every ASID, rather than only the one it was cached under
+        global: bool,
     },
 }
 
+impl Attr {
+    const DEVICE: u64 = 0;
+    const WRITE_BACK: u64 = 1;
+    const DEVICE_EARLY_ACK: u64 = 2;
+    const NON_CACHEABLE: u64 = 3;
+    const WRITE_THROUGH: u64 = 4;
+
+    /// Global, write-back cacheable memory accessible only by the kernel.
+    pub const fn kernel(read: bool, write: bool, execute: bool) -> Self {
+        Attr::Normal {
+            read,
+            write,
+            execute,
+            user: false,
+            user_execute: false,
+            cache: Cache::WriteBack,
+            global: true,
+        }
+    }
+
+    /// Non-global, write-back cacheable memory accessible by EL0. The kernel
+    /// shares its read and write permissions, but never executes it.
+    pub const fn user(read: bool, write: bool, execute: bool) -> Self {
+        Attr::Normal {
+            read,
+            write,
+            execute: false,
+            user: true,
+            user_execute: execute,
+            cache: Cache::WriteBack,
+            global: false,
+        }
+    }
+
+    /// Writable and executable at any exception level.
+    pub const fn is_writable_executable(&self) -> bool {
+        match self {
+            Attr::Device | Attr::DeviceEarlyAck => false,
+            Attr::Normal {
+                write,
+                execute,
+                user_execute,
+                ..
+            } => *write && (*execute || *user_execute),
+        }
+    }
+
+    /// Index into `MAIR_EL1`, as programmed by [`init`].
+    const fn index(&self) -> u64 {
+        match self {
+            Attr::Device => Self::DEVICE,
+            Attr::DeviceEarlyAck => Self::DEVICE_EARLY_ACK,
+            Attr::Normal { cache, .. } => match cache {
+                Cache::WriteBack => Self::WRITE_BACK,
+                Cache::WriteThrough => Self::WRITE_THROUGH,
+                Cache::NonCacheable => Self::NON_CACHEABLE,
+            },
+        }
+    }
+}
+
+/// Cacheability of normal memory, applied to both inner and outer caches.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Cache {
+    WriteBack,
+    WriteThrough,
+    /// Uncached, e.g. for buffers shared with non-coherent DMA masters
+    NonCacheable,
+}
+
 #[repr(transparent)]
 struct Table(InMemoryRegister<u64, table::Register>);
 
@@ -153,12 +1064,7 @@ impl Deref for Table {
     }
 }
 
-impl DerefMut for Table {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
-    }
-}
-
+/// Block or page descriptor, depending on level.
 #[repr(transparent)]
 struct Page(InMemoryRegister<u64, page::Register>);
 
@@ -169,16 +1075,12 @@ impl Deref for Page {
     }
 }
 
-impl DerefMut for Page {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
-    }
-}
-
+// Output addresses are stored from bit 12 regardless of granule, since
+// larger granules guarantee the extra low bits are zero.
 register_bitfields! {
     u64,
     table [
-        NEXT OFFSET(16) NUMBITS(32) [],
+        NEXT OFFSET(12) NUMBITS(36) [],
 
         TYPE OFFSET(1) NUMBITS(1) [
             Block = 0,
@@ -196,7 +1098,9 @@ register_bitfields! {
 
         PXN OFFSET(53) NUMBITS(1) [],
 
-        NEXT OFFSET(16) NUMBITS(32) [],
+        NEXT OFFSET(12) NUMBITS(36) [],
+
+        NG OFFSET(11) NUMBITS(1) [],
 
         AF OFFSET(10) NUMBITS(1) [
             False = 0,
@@ -220,7 +1124,7 @@ register_bitfields! {
         INDEX OFFSET(2) NUMBITS(3) [],
 
         TYPE OFFSET(1) NUMBITS(1) [
-            Reserved = 0,
+            Block = 0,
             Page = 1,
         ],
 
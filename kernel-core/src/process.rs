@@ -0,0 +1,480 @@
+use alloc::sync::Arc;
+use core::fmt::Debug;
+use core::ops::Range;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+
+use aarch64_cpu::asm::barrier;
+
+use crate::fs;
+use crate::interrupt;
+use crate::interrupt::Frame;
+use crate::mem;
+use crate::mem::PAGE_SIZE;
+use crate::mem::Phys;
+use crate::mem::User;
+use crate::mem::Virt;
+use crate::mmu;
+use crate::sync::Mutex;
+use crate::sync::MutexGuard;
+use crate::sync::Once;
+use crate::sync::WaitQueue;
+use crate::thread;
+use crate::thread::Thread;
+
+mod loader;
+
+/// Top of the user stack set up by [`Process::map_stack`].
+pub const STACK_TOP: u64 = 1 << 47;
+
+/// Bytes of user stack mapped by [`Process::map_stack`].
+pub const STACK_SIZE: u64 = 1 << 20;
+
+/// Start of the range that [`Process::map_anywhere`] allocates from, upward.
+pub const MMAP_BASE: u64 = 1 << 40;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Pid(u64);
+
+impl Pid {
+    pub fn value(self) -> u64 {
+        self.0
+    }
+}
+
+/// How a process ended.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Status {
+    Exited(i32),
+    /// Killed by an exception from EL0 that the kernel could not handle
+    Faulted {
+        elr: u64,
+        esr: u64,
+        far: u64,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    OutOfMemory,
+    /// Every ASID is in use
+    TooManyProcesses,
+    /// Misaligned, outside of the user address space, or not mapped
+    InvalidAddress,
+    AlreadyMapped,
+    /// Not a statically linked AArch64 ELF executable, or malformed
+    InvalidExecutable,
+}
+
+/// User address space, with its own page table in `TTBR0_EL1` tagged by its
+/// own ASID, in which user threads run at EL0.
+///
+/// Frames mapped into the page table belong to the process, and are freed
+/// with it.
+pub struct Process {
+    id: Pid,
+    root: Phys,
+    page_table: Mutex<mmu::PageTable<User>>,
+    asid: mmu::Asid,
+    /// End of the address space translated by `page_table`
+    limit: u64,
+    /// Next address for [`Process::map_anywhere`]
+    mmap: AtomicU64,
+    files: Mutex<fs::Files>,
+    status: Once<Status>,
+    exited: WaitQueue,
+}
+
+impl Process {
+    pub fn new() -> Result<Arc<Self>, Error> {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+
+        let asid = mmu::Asid::allocate().ok_or(Error::TooManyProcesses)?;
+        let page_table =
+            mmu::PageTable::new(mmu::Config::current(), mem::OFFSET, mem::alloc::global())
+                .ok_or(Error::OutOfMemory)?;
+
+        Ok(Arc::new(Self {
+            id: Pid(NEXT.fetch_add(1, Ordering::Relaxed)),
+            root: page_table.root(),
+            limit: 1 << page_table.config().user_bits,
+            page_table: Mutex::new(page_table),
+            asid,
+            mmap: AtomicU64::new(MMAP_BASE),
+            files: Mutex::new(fs::Files::console()),
+            status: Once::new(),
+            exited: WaitQueue::new(),
+        }))
+    }
+
+    pub fn id(&self) -> Pid {
+        self.id
+    }
+
+    /// End of this process's address space: user addresses at or above it
+    /// are not translated.
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// File descriptor table, which starts with the console open as
+    /// standard input, output, and error.
+    pub fn files(&self) -> MutexGuard<'_, fs::Files> {
+        self.files.lock()
+    }
+
+    /// Map `len` bytes of zeroed memory at `virt`, which must not overlap
+    /// existing mappings.
+    pub fn map(&self, virt: Virt<User>, len: u64, attr: mmu::Attr) -> Result<(), Error> {
+        let pages = self.pages(virt, len)?;
+        let mut page_table = self.page_table.lock();
+
+        if pages
+            .clone()
+            .step_by(PAGE_SIZE as usize)
+            .any(|page| page_table.translate(Virt::new(page)).is_some())
+        {
+            return Err(Error::AlreadyMapped);
+        }
+
+        for page in pages.clone().step_by(PAGE_SIZE as usize) {
+            let Some(frame) = mem::alloc::global().allocate().map(Phys::from) else {
+                Self::free(&mut page_table, pages.start..page);
+                return Err(Error::OutOfMemory);
+            };
+
+            unsafe {
+                frame
+                    .to_virt()
+                    .as_ptr::<u8>()
+                    .write_bytes(0, PAGE_SIZE as usize)
+            };
+            page_table.map(mem::alloc::global(), Virt::new(page), frame, attr);
+        }
+
+        Ok(())
+    }
+
+    /// Map `len` bytes of zeroed memory at an unused address, returning it.
+    pub fn map_anywhere(&self, len: u64, attr: mmu::Attr) -> Result<Virt<User>, Error> {
+        if len == 0 || len % PAGE_SIZE != 0 {
+            return Err(Error::InvalidAddress);
+        }
+
+        loop {
+            let virt = self.mmap.load(Ordering::Relaxed);
+            let end = virt
+                .checked_add(len)
+                .filter(|end| *end <= STACK_TOP - STACK_SIZE)
+                .ok_or(Error::OutOfMemory)?;
+
+            match self.map(Virt::new(virt), len, attr) {
+                Ok(()) => {
+                    // A racing caller may have already claimed and advanced past
+                    // a later range
+                    self.mmap.fetch_max(end, Ordering::Relaxed);
+                    return Ok(Virt::new(virt));
+                }
+                // Taken by a racing caller, which advances the cursor, or by a
+                // fixed mapping, which is skipped
+                Err(Error::AlreadyMapped) => {
+                    let _ =
+                        self.mmap
+                            .compare_exchange(virt, end, Ordering::Relaxed, Ordering::Relaxed);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Unmap and free `len` bytes of memory at `virt`, skipping pages that
+    /// are not mapped.
+    pub fn unmap(&self, virt: Virt<User>, len: u64) -> Result<(), Error> {
+        let pages = self.pages(virt, len)?;
+        Self::free(&mut self.page_table.lock(), pages);
+        Ok(())
+    }
+
+    /// Change the permissions of `len` bytes of memory at `virt`.
+    pub fn protect(&self, virt: Virt<User>, len: u64, attr: mmu::Attr) -> Result<(), Error> {
+        let pages = self.pages(virt, len)?;
+        self.page_table.lock().protect(
+            mem::alloc::global(),
+            Virt::new(pages.start)..Virt::new(pages.end),
+            attr,
+        );
+        Ok(())
+    }
+
+    /// Map [`STACK_SIZE`] bytes of stack below [`STACK_TOP`], returning its top.
+    pub fn map_stack(&self) -> Result<Virt<User>, Error> {
+        self.map(
+            Virt::new(STACK_TOP - STACK_SIZE),
+            STACK_SIZE,
+            mmu::Attr::user(true, true, false),
+        )?;
+        Ok(Virt::new(STACK_TOP))
+    }
+
+    /// Copy `bytes` into this process's memory at `virt`, regardless of the
+    /// permissions it is mapped with. The copy is made visible to instruction
+    /// fetch, so it may contain code.
+    pub fn write(&self, virt: Virt<User>, bytes: &[u8]) -> Result<(), Error> {
+        self.copy(virt, bytes.len(), Access::Kernel, |kernel, offset, len| {
+            unsafe { kernel.copy_from_nonoverlapping(bytes[offset..].as_ptr(), len) };
+            clean(kernel as u64, len);
+        })?;
+
+        barrier::dsb(barrier::ISH);
+        unsafe { core::arch::asm!("ic ialluis", options(nostack)) };
+        barrier::dsb(barrier::ISH);
+        barrier::isb(barrier::SY);
+        Ok(())
+    }
+
+    /// Copy from this process's memory at `virt` into `bytes`.
+    pub fn read(&self, virt: Virt<User>, bytes: &mut [u8]) -> Result<(), Error> {
+        self.copy(
+            virt,
+            bytes.len(),
+            Access::Kernel,
+            |kernel, offset, len| unsafe {
+                kernel.copy_to_nonoverlapping(bytes[offset..].as_mut_ptr(), len)
+            },
+        )
+    }
+
+    /// Like [`Process::read`], but only from memory readable at EL0.
+    pub(crate) fn copy_from_user(&self, virt: Virt<User>, bytes: &mut [u8]) -> Result<(), Error> {
+        self.copy(
+            virt,
+            bytes.len(),
+            Access::UserRead,
+            |kernel, offset, len| unsafe {
+                kernel.copy_to_nonoverlapping(bytes[offset..].as_mut_ptr(), len)
+            },
+        )
+    }
+
+    /// Like [`Process::write`], but only to memory writable at EL0.
+    pub(crate) fn copy_to_user(&self, virt: Virt<User>, bytes: &[u8]) -> Result<(), Error> {
+        self.copy(
+            virt,
+            bytes.len(),
+            Access::UserWrite,
+            |kernel, offset, len| unsafe {
+                kernel.copy_from_nonoverlapping(bytes[offset..].as_ptr(), len)
+            },
+        )
+    }
+
+    /// Check that `len` bytes at `virt` are writable at EL0, without copying.
+    pub(crate) fn check_user_writable(&self, virt: Virt<User>, len: usize) -> Result<(), Error> {
+        self.copy(virt, len, Access::UserWrite, |_, _, _| ())
+    }
+
+    /// Call `f` with the kernel address of each page-bounded chunk of `len`
+    /// bytes at `virt`, and the chunk's offset and length, once every page
+    /// is known to be mapped with `access`.
+    fn copy(
+        &self,
+        virt: Virt<User>,
+        len: usize,
+        access: Access,
+        mut f: impl FnMut(*mut u8, usize, usize),
+    ) -> Result<(), Error> {
+        let start = u64::from(virt);
+        let end = start
+            .checked_add(len as u64)
+            .filter(|end| *end <= self.limit)
+            .ok_or(Error::InvalidAddress)?;
+
+        let page_table = self.page_table.lock();
+
+        // Check every page before copying anything
+        let mut address = start;
+        while address < end {
+            match page_table.translate(Virt::new(address)) {
+                Some((_, attr)) if access.allows(attr) => (),
+                _ => return Err(Error::InvalidAddress),
+            }
+            address = (address & !(PAGE_SIZE - 1)) + PAGE_SIZE;
+        }
+
+        let mut address = start;
+        while address < end {
+            let chunk = (PAGE_SIZE - address % PAGE_SIZE).min(end - address);
+            let (phys, _) = page_table
+                .translate(Virt::new(address))
+                .ok_or(Error::InvalidAddress)?;
+
+            f(
+                phys.to_virt().as_ptr(),
+                (address - start) as usize,
+                chunk as usize,
+            );
+            address += chunk;
+        }
+
+        Ok(())
+    }
+
+    /// Start a thread at `entry` in EL0 with stack pointer `sp`, passing
+    /// `args` in registers starting from `x0`.
+    pub fn start(self: &Arc<Self>, entry: Virt<User>, sp: Virt<User>, args: &[u64]) -> Arc<Thread> {
+        let mut frame = Frame::user(entry, sp);
+        frame.x[..args.len()].copy_from_slice(args);
+
+        thread::spawn_in(Some(self.clone()), move || interrupt::enter_user(frame))
+            .thread()
+            .clone()
+    }
+
+    /// Record how the process ended and wake its waiters. Only the first
+    /// call has any effect.
+    pub fn exit(&self, status: Status) {
+        self.status.call_once(|| status);
+        self.exited.wake_all();
+    }
+
+    pub fn status(&self) -> Option<Status> {
+        self.status.get().copied()
+    }
+
+    /// Block until the process ends.
+    pub fn wait(&self) -> Status {
+        self.exited.wait_until(|| self.status())
+    }
+
+    /// Translate user addresses on this core through this process.
+    pub(crate) fn activate(&self) {
+        mmu::activate_user(self.root, &self.asid);
+    }
+
+    /// Validate a page-aligned range of user addresses.
+    fn pages(&self, virt: Virt<User>, len: u64) -> Result<Range<u64>, Error> {
+        let start = u64::from(virt);
+        if start % PAGE_SIZE != 0 || len % PAGE_SIZE != 0 {
+            return Err(Error::InvalidAddress);
+        }
+
+        let end = start
+            .checked_add(len)
+            .filter(|end| *end <= self.limit)
+            .ok_or(Error::InvalidAddress)?;
+
+        Ok(start..end)
+    }
+
+    fn free(page_table: &mut mmu::PageTable<User>, pages: Range<u64>) {
+        for page in pages.step_by(PAGE_SIZE as usize) {
+            if let Some(frame) = page_table.unmap(mem::alloc::global(), Virt::new(page)) {
+                mem::alloc::global().deallocate(frame.into());
+            }
+        }
+    }
+}
+
+impl Debug for Process {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Process")
+            .field("id", &self.id)
+            .field("asid", &self.asid)
+            .field("status", &self.status())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        // Cores that last ran the process may still cache its translations,
+        // which must be gone before the frames they point to are reused
+        self.asid.invalidate();
+
+        let page_table = self.page_table.get_mut();
+
+        for mapping in page_table.iter() {
+            for offset in (0..mapping.len).step_by(PAGE_SIZE as usize) {
+                let frame = Phys::new(u64::from(mapping.phys) + offset);
+                mem::alloc::global().deallocate(frame.into());
+            }
+        }
+
+        // The table is no longer installed on any core: its threads have all
+        // switched away, and TTBR0 walks are disabled for kernel threads
+        unsafe { mmu::PageTable::<User>::from_raw(self.root, page_table.config(), mem::OFFSET) }
+            .deallocate(mem::alloc::global());
+    }
+}
+
+/// Permissions required of each page by [`Process::copy`].
+#[derive(Copy, Clone, Debug)]
+enum Access {
+    /// Any mapping, since the kernel accesses it through the linear map
+    Kernel,
+    UserRead,
+    UserWrite,
+}
+
+impl Access {
+    fn allows(self, attr: mmu::Attr) -> bool {
+        match (self, attr) {
+            (Access::Kernel, _) => true,
+            (
+                Access::UserRead,
+                mmu::Attr::Normal {
+                    user: true, read, ..
+                },
+            ) => read,
+            (
+                Access::UserWrite,
+                mmu::Attr::Normal {
+                    user: true, write, ..
+                },
+            ) => write,
+            _ => false,
+        }
+    }
+}
+
+/// Write back data cache lines covering `len` bytes at `virt` to the point
+/// of unification, where instruction fetches observe them.
+fn clean(virt: u64, len: usize) {
+    const LINE: u64 = 64;
+
+    let mut line = virt & !(LINE - 1);
+    while line < virt + len as u64 {
+        unsafe {
+            core::arch::asm!("dc cvau, {line}", line = in(reg) line, options(nostack));
+        }
+        line += LINE;
+    }
+}
+
+/// Process of the current thread, if it is a user thread.
+pub fn current() -> Option<Arc<Process>> {
+    thread::current().process().cloned()
+}
+
+/// End the current process after an exception from EL0 that the kernel
+/// cannot handle.
+pub(crate) fn fault(frame: &Frame, esr: u64, far: u64) -> ! {
+    let process = current().expect("Exception from EL0 outside of a process");
+
+    warn!(
+        "Process {} faulted at {:#x}: ESR {:#x}, FAR {:#x}",
+        process.id().value(),
+        frame.elr,
+        esr,
+        far,
+    );
+
+    process.exit(Status::Faulted {
+        elr: frame.elr,
+        esr,
+        far,
+    });
+
+    drop(process);
+    thread::exit()
+}
@@ -0,0 +1,168 @@
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use elf::ElfBytes;
+use elf::endian::AnyEndian;
+use elf::file::Class;
+
+use super::Error;
+use super::MMAP_BASE;
+use super::Process;
+use super::STACK_SIZE;
+use crate::mem::PAGE_SIZE;
+use crate::mem::User;
+use crate::mem::Virt;
+use crate::mmu;
+use crate::thread::Thread;
+
+/// Auxiliary vector keys, as defined by the System V ABI.
+mod at {
+    pub const NULL: u64 = 0;
+    pub const PHDR: u64 = 3;
+    pub const PHENT: u64 = 4;
+    pub const PHNUM: u64 = 5;
+    pub const PAGESZ: u64 = 6;
+    pub const ENTRY: u64 = 9;
+}
+
+impl Process {
+    /// Load the statically linked AArch64 executable `image`, and start its
+    /// main thread at `e_entry` with `argv` and `envp`.
+    ///
+    /// The initial stack follows the System V ABI: `argc` at `sp`, followed
+    /// by the null-terminated `argv` and `envp` pointer arrays and the
+    /// auxiliary vector. `argc`, `argv`, and `envp` are also passed in `x0`
+    /// through `x2`.
+    ///
+    /// Loadable segments must not share pages, so they should be aligned
+    /// to the 64KiB granule.
+    pub fn exec(
+        self: &Arc<Self>,
+        image: &[u8],
+        argv: &[&[u8]],
+        envp: &[&[u8]],
+    ) -> Result<Arc<Thread>, Error> {
+        let elf =
+            ElfBytes::<AnyEndian>::minimal_parse(image).map_err(|_| Error::InvalidExecutable)?;
+
+        if elf.ehdr.class != Class::ELF64
+            || elf.ehdr.e_machine != elf::abi::EM_AARCH64
+            || elf.ehdr.e_type != elf::abi::ET_EXEC
+        {
+            return Err(Error::InvalidExecutable);
+        }
+
+        let segments = elf.segments().ok_or(Error::InvalidExecutable)?;
+        let mut phdr = None;
+
+        for segment in segments
+            .iter()
+            .filter(|segment| segment.p_type == elf::abi::PT_LOAD)
+        {
+            let attr = mmu::Attr::user(
+                segment.p_flags & elf::abi::PF_R > 0,
+                segment.p_flags & elf::abi::PF_W > 0,
+                segment.p_flags & elf::abi::PF_X > 0,
+            );
+
+            let end = segment
+                .p_vaddr
+                .checked_add(segment.p_memsz)
+                .filter(|end| *end <= MMAP_BASE)
+                .ok_or(Error::InvalidExecutable)?;
+
+            if attr.is_writable_executable() || segment.p_filesz > segment.p_memsz {
+                return Err(Error::InvalidExecutable);
+            }
+
+            let data = elf
+                .segment_data(&segment)
+                .map_err(|_| Error::InvalidExecutable)?;
+
+            // Mapped memory is zeroed, which covers BSS past `p_filesz`
+            let lo = segment.p_vaddr & !(PAGE_SIZE - 1);
+            let hi = end.next_multiple_of(PAGE_SIZE);
+            self.map(Virt::new(lo), hi - lo, mmu::Attr::user(true, true, false))?;
+            self.write(Virt::new(segment.p_vaddr), data)?;
+            self.protect(Virt::new(lo), hi - lo, attr)?;
+
+            // Program headers are visible to the program if covered by a segment
+            let phoff = elf.ehdr.e_phoff;
+            if phoff >= segment.p_offset && phoff - segment.p_offset < segment.p_filesz {
+                phdr = Some(segment.p_vaddr + (phoff - segment.p_offset));
+            }
+        }
+
+        let entry = elf.ehdr.e_entry;
+        if entry >= MMAP_BASE {
+            return Err(Error::InvalidExecutable);
+        }
+
+        let mut auxv = vec![
+            (at::PAGESZ, PAGE_SIZE),
+            (at::ENTRY, entry),
+            (at::PHENT, elf.ehdr.e_phentsize as u64),
+            (at::PHNUM, elf.ehdr.e_phnum as u64),
+        ];
+        auxv.extend(phdr.map(|phdr| (at::PHDR, phdr)));
+
+        let top = self.map_stack()?;
+        let (sp, args) = self.push_args(top, argv, envp, &auxv)?;
+        Ok(self.start(Virt::new(entry), sp, &args))
+    }
+
+    /// Copy `argv`, `envp`, and `auxv` onto the stack below `top`, returning
+    /// the new stack pointer and the arguments to `_start`.
+    fn push_args(
+        &self,
+        top: Virt<User>,
+        argv: &[&[u8]],
+        envp: &[&[u8]],
+        auxv: &[(u64, u64)],
+    ) -> Result<(Virt<User>, [u64; 3]), Error> {
+        let top = u64::from(top);
+
+        // Null-terminated strings at the top of the stack
+        let mut strings = Vec::new();
+        let mut offsets = Vec::with_capacity(argv.len() + envp.len());
+        for string in argv.iter().chain(envp) {
+            offsets.push(strings.len() as u64);
+            strings.extend_from_slice(string);
+            strings.push(0);
+        }
+
+        let words = 1 + (argv.len() + 1) + (envp.len() + 1) + 2 * (auxv.len() + 1);
+        let size = (strings.len() + words * size_of::<u64>()).next_multiple_of(16) as u64;
+        if size > STACK_SIZE / 2 {
+            return Err(Error::OutOfMemory);
+        }
+
+        let base = top - strings.len() as u64;
+        let sp = (base - (words * size_of::<u64>()) as u64) & !15;
+        let mut pointers = offsets.into_iter().map(|offset| base + offset);
+
+        let mut vector = Vec::with_capacity(words);
+        vector.push(argv.len() as u64);
+        vector.extend(pointers.by_ref().take(argv.len()));
+        vector.push(0);
+        vector.extend(pointers);
+        vector.push(0);
+        for (key, value) in auxv.iter().chain([&(at::NULL, 0)]) {
+            vector.extend([*key, *value]);
+        }
+
+        let bytes = vector
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect::<Vec<_>>();
+
+        self.write(Virt::new(base), &strings)?;
+        self.write(Virt::new(sp), &bytes)?;
+
+        let argc = argv.len() as u64;
+        let argv = sp + size_of::<u64>() as u64;
+        let envp = argv + (argc + 1) * size_of::<u64>() as u64;
+        Ok((Virt::new(sp), [argc, argv, envp]))
+    }
+}
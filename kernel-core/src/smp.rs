@@ -0,0 +1,357 @@
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+use core::time::Duration;
+
+use aarch64_cpu::asm::barrier;
+use aarch64_cpu::registers::MAIR_EL1;
+use aarch64_cpu::registers::MPIDR_EL1;
+use aarch64_cpu::registers::Readable as _;
+use aarch64_cpu::registers::SCTLR_EL1;
+use aarch64_cpu::registers::TCR_EL1;
+use aarch64_cpu::registers::TPIDR_EL1;
+use aarch64_cpu::registers::TTBR1_EL1;
+use aarch64_cpu::registers::Writeable as _;
+
+use crate::mem::Kernel;
+use crate::mem::Phys;
+use crate::mem::User;
+use crate::mem::Virt;
+use crate::mem::alloc;
+use crate::mem::stack::Stack;
+use crate::mmu;
+use crate::time;
+
+mod ipi;
+
+pub use ipi::Message;
+pub use ipi::broadcast;
+pub use ipi::call;
+pub(crate) use ipi::handle as handle_ipi;
+pub use ipi::register;
+pub use ipi::send_ipi;
+
+/// Number of cores on the BCM2837B0.
+pub const CPUS: usize = 4;
+
+/// Data private to each core, found through `TPIDR_EL1`.
+pub struct Cpu {
+    id: usize,
+    online: AtomicBool,
+}
+
+impl Cpu {
+    const fn new(id: usize) -> Self {
+        Self {
+            id,
+            online: AtomicBool::new(false),
+        }
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.online.load(Ordering::Acquire)
+    }
+}
+
+static CPU: [Cpu; CPUS] = [Cpu::new(0), Cpu::new(1), Cpu::new(2), Cpu::new(3)];
+
+/// One value per core, indexed by [`cpu_id`].
+pub struct PerCpu<T>([T; CPUS]);
+
+impl<T> PerCpu<T> {
+    pub const fn new(values: [T; CPUS]) -> Self {
+        Self(values)
+    }
+
+    /// Value for the current core.
+    ///
+    /// The caller is responsible for not migrating to another core while
+    /// using the value, e.g. by masking interrupts.
+    pub fn get(&self) -> &T {
+        &self.0[cpu_id()]
+    }
+
+    pub fn of(&self, cpu: usize) -> &T {
+        &self.0[cpu]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.0.iter()
+    }
+}
+
+/// Per-core data of the current core.
+pub fn cpu() -> &'static Cpu {
+    let cpu = TPIDR_EL1.get() as *const Cpu;
+    assert!(!cpu.is_null(), "Per-CPU data not initialized");
+    unsafe { &*cpu }
+}
+
+pub fn cpu_id() -> usize {
+    cpu().id
+}
+
+/// Iterate over cores that have finished bring-up.
+pub fn online() -> impl Iterator<Item = usize> {
+    CPU.iter().filter(|cpu| cpu.is_online()).map(Cpu::id)
+}
+
+/// Point `TPIDR_EL1` at the current core's per-CPU data and mark it online.
+///
+/// # Safety
+///
+/// Must be called exactly once per core, before any other function in this module.
+pub(crate) unsafe fn init_cpu() {
+    let id = (MPIDR_EL1.get() & 0xFF) as usize;
+    let cpu = &CPU[id];
+    TPIDR_EL1.set(cpu as *const Cpu as u64);
+    cpu.online.store(true, Ordering::Release);
+}
+
+/// Parameters for the next secondary core to start, read by the trampoline
+/// at `__SECONDARY_LO` with the MMU and data cache disabled.
+///
+/// Secondaries are started one at a time, so a single instance suffices.
+#[repr(C, align(64))]
+struct Boot {
+    mair: AtomicU64,
+    tcr: AtomicU64,
+    ttbr0: AtomicU64,
+    ttbr1: AtomicU64,
+    sctlr: AtomicU64,
+    stack: AtomicU64,
+    entry: AtomicU64,
+    main: AtomicU64,
+}
+
+static BOOT: Boot = Boot {
+    mair: AtomicU64::new(0),
+    tcr: AtomicU64::new(0),
+    ttbr0: AtomicU64::new(0),
+    ttbr1: AtomicU64::new(0),
+    sctlr: AtomicU64::new(0),
+    stack: AtomicU64::new(0),
+    entry: AtomicU64::new(0),
+    main: AtomicU64::new(0),
+};
+
+unsafe extern "C" {
+    static __SECONDARY_LO: u32;
+    static __SECONDARY_HI: u32;
+}
+
+/// Release the secondary cores listed in the device tree, each of which
+/// runs `main` on its own kernel stack with the kernel page table.
+///
+/// Cores are released through the spin table: the firmware parks each one
+/// polling its `cpu-release-addr` until a physical entry point is written.
+pub fn start(device_tree: &device_tree::Blob, main: extern "C" fn() -> !) {
+    let allocator = alloc::global();
+    let config = mmu::Config::current();
+    let granule = config.granule.size();
+
+    // Secondaries enable the MMU while running at physical addresses,
+    // so the trampoline must be identity mapped
+    let virt = |address: *const u32| Virt::<Kernel>::new(address as u64).to_phys();
+    let trampoline = unsafe { virt(&__SECONDARY_LO)..virt(&__SECONDARY_HI) };
+    let lo = u64::from(trampoline.start) & !(granule - 1);
+    let hi = u64::from(trampoline.end).next_multiple_of(granule);
+
+    let mut identity = mmu::PageTable::<User>::new(config, crate::mem::OFFSET, allocator)
+        .expect("Out of memory for page table");
+    identity.map_range(
+        allocator,
+        Virt::new(lo),
+        Phys::new(lo),
+        hi - lo,
+        mmu::Attr::kernel(true, false, true),
+    );
+
+    // TTBR0 walks are disabled on this core after `mmu::unmap_identity`
+    let tcr = TCR_EL1.get() & !TCR_EL1::EPD0::DisableTTBR0Walks.value;
+
+    BOOT.mair.store(MAIR_EL1.get(), Ordering::Relaxed);
+    BOOT.tcr.store(tcr, Ordering::Relaxed);
+    BOOT.ttbr0
+        .store(u64::from(identity.root()), Ordering::Relaxed);
+    BOOT.ttbr1.store(TTBR1_EL1.get(), Ordering::Relaxed);
+    BOOT.sctlr.store(SCTLR_EL1.get(), Ordering::Relaxed);
+    BOOT.entry
+        .store(start_secondary as usize as u64, Ordering::Relaxed);
+    BOOT.main.store(main as usize as u64, Ordering::Relaxed);
+
+    let mut stuck = false;
+
+    for node in device_tree
+        .root()
+        .cpus()
+        .children()
+        .filter(|node| node.name().starts_with("cpu@"))
+    {
+        let Some(id) = node
+            .reg()
+            .and_then(|reg| reg.iter().next())
+            .map(|reg| reg.address as usize)
+        else {
+            warn!("Missing reg for {}", node.name());
+            continue;
+        };
+
+        if id >= CPUS {
+            warn!("Ignoring CPU {}", id);
+            continue;
+        }
+
+        if CPU[id].is_online() {
+            continue;
+        }
+
+        let method = node
+            .prop("enable-method")
+            .and_then(|prop| prop.as_strs().iter().next());
+        if method != Some("spin-table") {
+            warn!("Unsupported enable method for CPU {}: {:?}", id, method);
+            continue;
+        }
+
+        let Some(release) = node.prop("cpu-release-addr").and_then(|prop| prop.as_u64()) else {
+            warn!("Missing cpu-release-addr for CPU {}", id);
+            continue;
+        };
+
+        let stack = Stack::new().expect("Failed to allocate kernel stack");
+        BOOT.stack.store(u64::from(stack.leak()), Ordering::Relaxed);
+        clean(&BOOT as *const Boot as u64, size_of::<Boot>());
+
+        let release = Phys::new(release).to_virt();
+        unsafe {
+            release
+                .as_ptr::<u64>()
+                .write_volatile(u64::from(trampoline.start))
+        };
+        clean(u64::from(release), size_of::<u64>());
+        aarch64_cpu::asm::sev();
+
+        let deadline = time::Instant::now() + Duration::from_secs(1);
+        while !CPU[id].is_online() && time::Instant::now() < deadline {
+            crate::pause();
+        }
+
+        match CPU[id].is_online() {
+            true => info!("Started CPU {}", id),
+            false => {
+                warn!("Timed out starting CPU {}", id);
+                stuck = true;
+            }
+        }
+    }
+
+    // A core that timed out may still be reading the identity map
+    if !stuck {
+        identity.deallocate(allocator);
+    }
+}
+
+/// Write back data cache lines covering `len` bytes at `virt` to the point
+/// of coherency, for observers with caches disabled.
+fn clean(virt: u64, len: usize) {
+    const LINE: u64 = 64;
+
+    let mut line = virt & !(LINE - 1);
+    while line < virt + len as u64 {
+        unsafe {
+            core::arch::asm!("dc cvac, {line}", line = in(reg) line, options(nostack));
+        }
+        line += LINE;
+    }
+
+    barrier::dsb(barrier::SY);
+}
+
+extern "C" fn start_secondary(main: extern "C" fn() -> !) -> ! {
+    mmu::uninstall_user();
+
+    unsafe {
+        init_cpu();
+        crate::interrupt::init_cpu();
+    }
+
+    main()
+}
+
+// Entered from the spin table at a physical address with the MMU off, at
+// EL2 (from the firmware) or EL1. The firmware has already set
+// CPUECTLR_EL1.SMPEN, so caches are coherent once enabled.
+core::arch::global_asm! {
+r#"
+.pushsection .text.secondary, "ax"
+.balign 8
+.global __SECONDARY_LO
+.global __SECONDARY_HI
+__SECONDARY_LO:
+    mrs x0, CurrentEL
+    cmp x0, (2 << 2)
+    b.ne 1f
+
+    # EL1 access to the physical timer and counter
+    mov x0, 0b11
+    msr CNTHCTL_EL2, x0
+    msr CNTVOFF_EL2, xzr
+
+    # RW: EL1 is AArch64
+    mov x0, (1 << 31)
+    msr HCR_EL2, x0
+
+    # EL1h with DAIF masked
+    mov x0, 0x3C5
+    msr SPSR_EL2, x0
+    adr x0, 1f
+    msr ELR_EL2, x0
+    eret
+
+1:
+    # PC-relative, so the physical address of BOOT
+    adrp x9, {BOOT}
+    add x9, x9, :lo12:{BOOT}
+
+    ldr x1, [x9, {MAIR}]
+    ldr x2, [x9, {TCR}]
+    ldr x3, [x9, {TTBR0}]
+    ldr x4, [x9, {TTBR1}]
+    ldr x5, [x9, {SCTLR}]
+    ldr x6, [x9, {STACK}]
+    ldr x7, [x9, {ENTRY}]
+    ldr x0, [x9, {MAIN}]
+
+    msr MAIR_EL1, x1
+    msr TCR_EL1, x2
+    msr TTBR0_EL1, x3
+    msr TTBR1_EL1, x4
+    isb
+    tlbi vmalle1
+    dsb nsh
+    isb
+
+    msr SCTLR_EL1, x5
+    isb
+
+    msr SPSel, 1
+    mov sp, x6
+    br x7
+__SECONDARY_HI:
+.popsection
+"#,
+    BOOT = sym BOOT,
+    MAIR = const core::mem::offset_of!(Boot, mair),
+    TCR = const core::mem::offset_of!(Boot, tcr),
+    TTBR0 = const core::mem::offset_of!(Boot, ttbr0),
+    TTBR1 = const core::mem::offset_of!(Boot, ttbr1),
+    SCTLR = const core::mem::offset_of!(Boot, sctlr),
+    STACK = const core::mem::offset_of!(Boot, stack),
+    ENTRY = const core::mem::offset_of!(Boot, entry),
+    MAIN = const core::mem::offset_of!(Boot, main),
+}
@@ -0,0 +1,117 @@
+//! Inter-processor interrupts through the BCM2836 local core mailboxes, used
+//! for reschedule kicks and cross-core function calls.
+//!
+//! There is no TLB shootdown message. Every TLB invalidation the kernel makes
+//! is broadcast to the inner shareable domain in hardware, which all four
+//! cores belong to: `tlbi vaae1is` when a kernel or user mapping changes (see
+//! [`crate::mmu::PageTable`]), and `tlbi aside1is` when a process's address
+//! space is torn down (see [`crate::mmu::Asid`]). The `dsb ish` that follows
+//! each one waits for every core to complete it, which is what a shootdown IPI
+//! would otherwise wait for, without requiring the other cores to have
+//! interrupts unmasked.
+
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+
+use aarch64_cpu::asm::barrier;
+use arrayvec::ArrayVec;
+
+use crate::device::bcm2837b0::ic;
+use crate::smp::PerCpu;
+use crate::smp::cpu_id;
+use crate::smp::online;
+use crate::sync::IrqSpinLock;
+
+/// Inter-processor interrupt, delivered through mailbox 0 of the target
+/// core with one bit per message.
+///
+/// Messages are coalesced: sending a message that is already pending on the
+/// target has no additional effect.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(usize)]
+pub enum Message {
+    /// Ask the target to reconsider what it is running.
+    Reschedule,
+    /// Run functions queued for the target (see [`call`]).
+    Call,
+}
+
+const MESSAGES: usize = 2;
+
+const MAILBOX: usize = 0;
+
+/// Handlers registered per message, stored as `fn()` pointers (zero if unset).
+static HANDLERS: [AtomicUsize; MESSAGES] = [const { AtomicUsize::new(0) }; MESSAGES];
+
+type Calls = ArrayVec<fn(), 16>;
+
+/// Functions queued for each core by [`call`], which are also drained in
+/// interrupt context by [`handle`].
+static CALLS: PerCpu<IrqSpinLock<Calls>> =
+    PerCpu::new([const { IrqSpinLock::new(ArrayVec::new_const()) }; super::CPUS]);
+
+fn controller() -> ic::Core {
+    unsafe { ic::Core::new(0x4000_0000) }
+}
+
+/// Run `handler` when the current core, or any other, receives `message`,
+/// after its built-in handling. Replaces any previously registered handler.
+pub fn register(message: Message, handler: fn()) {
+    HANDLERS[message as usize].store(handler as usize, Ordering::Release);
+}
+
+pub fn send_ipi(cpu: usize, message: Message) {
+    // Make prior writes visible before the target can observe the interrupt
+    barrier::dsb(barrier::ISHST);
+    controller().send(cpu, MAILBOX, 1 << message as usize);
+}
+
+/// Send `message` to every other online core.
+pub fn broadcast(message: Message) {
+    let id = cpu_id();
+    online()
+        .filter(|cpu| *cpu != id)
+        .for_each(|cpu| send_ipi(cpu, message));
+}
+
+/// Run `f` in interrupt context on `cpu`, or directly if `cpu` is the current
+/// core. Returns without waiting for `f` to run.
+pub fn call(cpu: usize, f: fn()) {
+    if cpu == cpu_id() {
+        f();
+        return;
+    }
+
+    CALLS
+        .of(cpu)
+        .lock()
+        .try_push(f)
+        .expect("Too many pending cross-core calls");
+
+    send_ipi(cpu, Message::Call);
+}
+
+/// Dispatch messages pending for the current core.
+pub(crate) fn handle() {
+    let pending = controller().take(cpu_id(), MAILBOX);
+
+    for message in [Message::Reschedule, Message::Call] {
+        if pending & (1 << message as usize) == 0 {
+            continue;
+        }
+
+        match message {
+            Message::Reschedule => crate::thread::reschedule(),
+            Message::Call => {
+                let calls = core::mem::take(&mut *CALLS.get().lock());
+                calls.into_iter().for_each(|f| f());
+            }
+        }
+
+        let handler = HANDLERS[message as usize].load(Ordering::Acquire);
+        if handler != 0 {
+            let handler = unsafe { core::mem::transmute::<usize, fn()>(handler) };
+            handler();
+        }
+    }
+}
@@ -1,68 +1,28 @@
-// use core::sync::atomic::A
-
-use core::cell::UnsafeCell;
-use core::ops::Deref;
-use core::ops::DerefMut;
-use core::sync::atomic::AtomicBool;
-use core::sync::atomic::Ordering;
-
-pub struct SpinLock<T> {
-    lock: AtomicBool,
-    inner: UnsafeCell<T>,
-}
-
-unsafe impl<T: Send> Sync for SpinLock<T> {}
-unsafe impl<T: Send> Send for SpinLock<T> {}
-
-impl<T> SpinLock<T> {
-    pub const fn new(inner: T) -> Self {
-        Self {
-            lock: AtomicBool::new(false),
-            inner: UnsafeCell::new(inner),
-        }
-    }
-
-    pub fn lock(&self) -> SpinLockGuard<T> {
-        loop {
-            while self.lock.load(Ordering::Relaxed) {
-                crate::pause();
-            }
-
-            if let Ok(false) =
-                self.lock
-                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
-            {
-                break;
-            }
-        }
-
-        SpinLockGuard {
-            lock: self,
-            inner: unsafe { self.inner.get().as_mut().unwrap() },
-        }
-    }
-}
-
-pub struct SpinLockGuard<'a, T> {
-    lock: &'a SpinLock<T>,
-    inner: &'a mut T,
-}
-
-impl<T> Deref for SpinLockGuard<'_, T> {
-    type Target = T;
-    fn deref(&self) -> &Self::Target {
-        self.inner
-    }
-}
-
-impl<T> DerefMut for SpinLockGuard<'_, T> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        self.inner
-    }
-}
-
-impl<T> Drop for SpinLockGuard<'_, T> {
-    fn drop(&mut self) {
-        self.lock.lock.store(false, Ordering::Release);
-    }
-}
+mod channel;
+mod condvar;
+mod irq;
+mod mutex;
+mod once;
+mod rw;
+mod semaphore;
+mod spin;
+mod ticket;
+mod wait;
+
+pub use channel::Channel;
+pub use condvar::Condvar;
+pub use irq::IrqSpinLock;
+pub use irq::IrqSpinLockGuard;
+pub use mutex::Mutex;
+pub use mutex::MutexGuard;
+pub use once::LazyLock;
+pub use once::Once;
+pub use rw::RwLock;
+pub use rw::RwLockReadGuard;
+pub use rw::RwLockWriteGuard;
+pub use semaphore::Semaphore;
+pub use spin::SpinLock;
+pub use spin::SpinLockGuard;
+pub use ticket::TicketLock;
+pub use ticket::TicketLockGuard;
+pub use wait::WaitQueue;
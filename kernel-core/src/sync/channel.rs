@@ -0,0 +1,77 @@
+use alloc::collections::VecDeque;
+
+use crate::sync::IrqSpinLock;
+use crate::sync::WaitQueue;
+
+/// Bounded multi-producer, multi-consumer queue.
+///
+/// Shared by reference, so typically a `static` or behind an `Arc`. The
+/// non-blocking [`Channel::try_send`] and [`Channel::try_recv`] are safe to
+/// call from interrupt handlers.
+pub struct Channel<T> {
+    queue: IrqSpinLock<VecDeque<T>>,
+    capacity: usize,
+    not_empty: WaitQueue,
+    not_full: WaitQueue,
+}
+
+impl<T> Channel<T> {
+    pub const fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "Channel must have nonzero capacity");
+        Self {
+            queue: IrqSpinLock::new(VecDeque::new()),
+            capacity,
+            not_empty: WaitQueue::new(),
+            not_full: WaitQueue::new(),
+        }
+    }
+
+    /// Queue `value`, blocking while the channel is full.
+    pub fn send(&self, value: T) {
+        let mut value = Some(value);
+        self.not_full.wait_until(|| {
+            let mut queue = self.queue.lock();
+            (queue.len() < self.capacity).then(|| queue.push_back(value.take().unwrap()))
+        });
+        self.not_empty.wake_one();
+    }
+
+    /// Queue `value` if the channel has room, or return it otherwise.
+    pub fn try_send(&self, value: T) -> Result<(), T> {
+        {
+            let mut queue = self.queue.lock();
+            if queue.len() >= self.capacity {
+                return Err(value);
+            }
+            queue.push_back(value);
+        }
+
+        self.not_empty.wake_one();
+        Ok(())
+    }
+
+    /// Take the oldest value, blocking while the channel is empty.
+    pub fn recv(&self) -> T {
+        let value = self.not_empty.wait_until(|| self.queue.lock().pop_front());
+        self.not_full.wake_one();
+        value
+    }
+
+    pub fn try_recv(&self) -> Option<T> {
+        let value = self.queue.lock().pop_front()?;
+        self.not_full.wake_one();
+        Some(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
@@ -0,0 +1,66 @@
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+
+use crate::sync::MutexGuard;
+use crate::sync::WaitQueue;
+
+/// Condition variable, for blocking on a condition protected by a
+/// [`super::Mutex`].
+///
+/// Wakeups may be spurious, so callers should recheck their condition, e.g.
+/// with [`Condvar::wait_while`].
+pub struct Condvar {
+    /// Incremented by each notification, so waiters can tell whether they
+    /// missed one between unlocking the mutex and blocking
+    generation: AtomicU64,
+    waiters: WaitQueue,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            generation: AtomicU64::new(0),
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Unlock `guard`'s mutex and block until notified, then lock it again.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let generation = self.generation.load(Ordering::Acquire);
+        let mutex = guard.mutex();
+        drop(guard);
+
+        self.waiters
+            .wait_until(|| (self.generation.load(Ordering::Acquire) != generation).then_some(()));
+
+        mutex.lock()
+    }
+
+    /// Block while `condition` holds for the protected data.
+    pub fn wait_while<'a, T>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> MutexGuard<'a, T> {
+        while condition(&mut guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    pub fn notify_one(&self) {
+        self.generation.fetch_add(1, Ordering::Release);
+        self.waiters.wake_one();
+    }
+
+    pub fn notify_all(&self) {
+        self.generation.fetch_add(1, Ordering::Release);
+        self.waiters.wake_all();
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,72 @@
+use core::mem::ManuallyDrop;
+use core::ops::Deref;
+use core::ops::DerefMut;
+
+use crate::interrupt;
+use crate::sync::SpinLock;
+use crate::sync::SpinLockGuard;
+
+/// Spin lock that masks IRQs on the current core while held, so it can be
+/// shared between interrupt handlers and the code they interrupt without
+/// deadlocking.
+pub struct IrqSpinLock<T> {
+    inner: SpinLock<T>,
+}
+
+impl<T> IrqSpinLock<T> {
+    pub const fn new(inner: T) -> Self {
+        Self {
+            inner: SpinLock::new(inner),
+        }
+    }
+
+    pub fn lock(&self) -> IrqSpinLockGuard<T> {
+        let mask = interrupt::disable();
+        IrqSpinLockGuard {
+            guard: ManuallyDrop::new(self.inner.lock()),
+            mask: ManuallyDrop::new(mask),
+        }
+    }
+
+    pub fn try_lock(&self) -> Option<IrqSpinLockGuard<T>> {
+        let mask = interrupt::disable();
+        match self.inner.try_lock() {
+            Some(guard) => Some(IrqSpinLockGuard {
+                guard: ManuallyDrop::new(guard),
+                mask: ManuallyDrop::new(mask),
+            }),
+            None => {
+                interrupt::restore(mask);
+                None
+            }
+        }
+    }
+}
+
+pub struct IrqSpinLockGuard<'a, T> {
+    guard: ManuallyDrop<SpinLockGuard<'a, T>>,
+    mask: ManuallyDrop<interrupt::Mask>,
+}
+
+impl<T> Deref for IrqSpinLockGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for IrqSpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for IrqSpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        // Release the lock before an interrupt handler can try to take it
+        unsafe {
+            ManuallyDrop::drop(&mut self.guard);
+            interrupt::restore(ManuallyDrop::take(&mut self.mask));
+        }
+    }
+}
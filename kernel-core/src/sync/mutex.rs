@@ -0,0 +1,87 @@
+use core::cell::UnsafeCell;
+use core::ops::Deref;
+use core::ops::DerefMut;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering;
+
+use crate::sync::WaitQueue;
+
+/// Lock that blocks contending threads instead of spinning.
+///
+/// Must not be used from interrupt handlers, which cannot block.
+pub struct Mutex<T> {
+    lock: AtomicBool,
+    waiters: WaitQueue,
+    inner: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for Mutex<T> {}
+unsafe impl<T: Send> Send for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(inner: T) -> Self {
+        Self {
+            lock: AtomicBool::new(false),
+            waiters: WaitQueue::new(),
+            inner: UnsafeCell::new(inner),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<T> {
+        match self.try_lock() {
+            Some(guard) => guard,
+            None => self.waiters.wait_until(|| self.try_lock()),
+        }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<T>> {
+        self.lock
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+
+        Some(MutexGuard {
+            lock: self,
+            inner: unsafe { self.inner.get().as_mut().unwrap() },
+        })
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+}
+
+pub struct MutexGuard<'a, T> {
+    lock: &'a Mutex<T>,
+    inner: &'a mut T,
+}
+
+impl<'a, T> MutexGuard<'a, T> {
+    /// Mutex this guard locks, for [`super::Condvar`] to reacquire.
+    pub(super) fn mutex(&self) -> &'a Mutex<T> {
+        self.lock
+    }
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        self.inner
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.inner
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.lock.store(false, Ordering::Release);
+        self.lock.waiters.wake_one();
+    }
+}
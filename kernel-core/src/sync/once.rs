@@ -0,0 +1,113 @@
+use core::cell::Cell;
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::ops::Deref;
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::Ordering;
+
+/// Value initialized at most once, by whichever caller gets there first.
+/// Concurrent callers spin until it is ready.
+pub struct Once<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send + Sync> Sync for Once<T> {}
+unsafe impl<T: Send> Send for Once<T> {}
+
+impl<T> Once<T> {
+    const INCOMPLETE: u8 = 0;
+    const RUNNING: u8 = 1;
+    const COMPLETE: u8 = 2;
+
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(Self::INCOMPLETE),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Initialize the value with `f` if no one has yet, and return it.
+    ///
+    /// `f` must not call back into this `Once`, which would spin forever.
+    pub fn call_once(&self, f: impl FnOnce() -> T) -> &T {
+        match self.state.compare_exchange(
+            Self::INCOMPLETE,
+            Self::RUNNING,
+            Ordering::Acquire,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => {
+                unsafe { (*self.value.get()).write(f()) };
+                self.state.store(Self::COMPLETE, Ordering::Release);
+            }
+            Err(_) => {
+                while self.state.load(Ordering::Acquire) != Self::COMPLETE {
+                    crate::pause();
+                }
+            }
+        }
+
+        unsafe { (*self.value.get()).assume_init_ref() }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        match self.state.load(Ordering::Acquire) {
+            Self::COMPLETE => Some(unsafe { (*self.value.get()).assume_init_ref() }),
+            _ => None,
+        }
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == Self::COMPLETE
+    }
+}
+
+impl<T> Default for Once<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Once<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == Self::COMPLETE {
+            unsafe { self.value.get_mut().assume_init_drop() }
+        }
+    }
+}
+
+/// Value initialized by `F` on first access.
+pub struct LazyLock<T, F = fn() -> T> {
+    once: Once<T>,
+    init: Cell<Option<F>>,
+}
+
+unsafe impl<T: Send + Sync, F: Send> Sync for LazyLock<T, F> {}
+
+impl<T, F: FnOnce() -> T> LazyLock<T, F> {
+    pub const fn new(init: F) -> Self {
+        Self {
+            once: Once::new(),
+            init: Cell::new(Some(init)),
+        }
+    }
+
+    pub fn force(this: &Self) -> &T {
+        this.once.call_once(|| {
+            // Only the first caller of `call_once` reaches here
+            let init = this
+                .init
+                .take()
+                .expect("LazyLock initializer already taken");
+            init()
+        })
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for LazyLock<T, F> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        Self::force(self)
+    }
+}
@@ -0,0 +1,122 @@
+use core::cell::UnsafeCell;
+use core::ops::Deref;
+use core::ops::DerefMut;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+
+/// Spinning reader-writer lock.
+///
+/// Readers are admitted whenever no writer holds the lock, so a steady stream
+/// of readers can starve writers.
+pub struct RwLock<T> {
+    /// Number of readers, or [`RwLock::WRITER`] if held for writing
+    state: AtomicUsize,
+    inner: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send + Sync> Sync for RwLock<T> {}
+unsafe impl<T: Send> Send for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    const WRITER: usize = usize::MAX;
+
+    pub const fn new(inner: T) -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            inner: UnsafeCell::new(inner),
+        }
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<T> {
+        loop {
+            if let Some(guard) = self.try_read() {
+                return guard;
+            }
+
+            crate::pause();
+        }
+    }
+
+    pub fn try_read(&self) -> Option<RwLockReadGuard<T>> {
+        self.state
+            .fetch_update(
+                Ordering::Acquire,
+                Ordering::Relaxed,
+                |readers| match readers {
+                    Self::WRITER => None,
+                    readers => Some(readers + 1),
+                },
+            )
+            .ok()?;
+
+        Some(RwLockReadGuard {
+            lock: self,
+            inner: unsafe { self.inner.get().as_ref().unwrap() },
+        })
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<T> {
+        loop {
+            while self.state.load(Ordering::Relaxed) != 0 {
+                crate::pause();
+            }
+
+            if let Some(guard) = self.try_write() {
+                return guard;
+            }
+        }
+    }
+
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<T>> {
+        self.state
+            .compare_exchange(0, Self::WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+
+        Some(RwLockWriteGuard {
+            lock: self,
+            inner: unsafe { self.inner.get().as_mut().unwrap() },
+        })
+    }
+}
+
+pub struct RwLockReadGuard<'a, T> {
+    lock: &'a RwLock<T>,
+    inner: &'a T,
+}
+
+impl<T> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        self.inner
+    }
+}
+
+impl<T> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(1, Ordering::Release);
+    }
+}
+
+pub struct RwLockWriteGuard<'a, T> {
+    lock: &'a RwLock<T>,
+    inner: &'a mut T,
+}
+
+impl<T> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        self.inner
+    }
+}
+
+impl<T> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.inner
+    }
+}
+
+impl<T> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.store(0, Ordering::Release);
+    }
+}
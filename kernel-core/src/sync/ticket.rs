@@ -0,0 +1,86 @@
+use core::cell::UnsafeCell;
+use core::ops::Deref;
+use core::ops::DerefMut;
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::Ordering;
+
+/// Spin lock that grants access in the order it was requested, so no core
+/// can be starved by others repeatedly winning the race.
+pub struct TicketLock<T> {
+    next: AtomicU32,
+    serving: AtomicU32,
+    inner: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for TicketLock<T> {}
+unsafe impl<T: Send> Send for TicketLock<T> {}
+
+impl<T> TicketLock<T> {
+    pub const fn new(inner: T) -> Self {
+        Self {
+            next: AtomicU32::new(0),
+            serving: AtomicU32::new(0),
+            inner: UnsafeCell::new(inner),
+        }
+    }
+
+    pub fn lock(&self) -> TicketLockGuard<T> {
+        let ticket = self.next.fetch_add(1, Ordering::Relaxed);
+
+        while self.serving.load(Ordering::Acquire) != ticket {
+            crate::pause();
+        }
+
+        TicketLockGuard {
+            lock: self,
+            inner: unsafe { self.inner.get().as_mut().unwrap() },
+        }
+    }
+
+    /// Acquire the lock only if no one holds or is waiting for it.
+    pub fn try_lock(&self) -> Option<TicketLockGuard<T>> {
+        let serving = self.serving.load(Ordering::Acquire);
+
+        self.next
+            .compare_exchange(
+                serving,
+                serving.wrapping_add(1),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .ok()?;
+
+        Some(TicketLockGuard {
+            lock: self,
+            inner: unsafe { self.inner.get().as_mut().unwrap() },
+        })
+    }
+}
+
+pub struct TicketLockGuard<'a, T> {
+    lock: &'a TicketLock<T>,
+    inner: &'a mut T,
+}
+
+impl<T> Deref for TicketLockGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        self.inner
+    }
+}
+
+impl<T> DerefMut for TicketLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.inner
+    }
+}
+
+impl<T> Drop for TicketLockGuard<'_, T> {
+    fn drop(&mut self) {
+        // Only the holder modifies `serving`
+        let serving = self.lock.serving.load(Ordering::Relaxed);
+        self.lock
+            .serving
+            .store(serving.wrapping_add(1), Ordering::Release);
+    }
+}
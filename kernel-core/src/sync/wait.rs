@@ -0,0 +1,67 @@
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+use crate::interrupt;
+use crate::sync::IrqSpinLock;
+use crate::thread;
+use crate::thread::Thread;
+
+/// Threads blocked until some condition holds, which whoever changes the
+/// condition is responsible for waking.
+pub struct WaitQueue {
+    waiters: IrqSpinLock<VecDeque<Arc<Thread>>>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            waiters: IrqSpinLock::new(VecDeque::new()),
+        }
+    }
+
+    /// Block until `condition` returns `Some`.
+    ///
+    /// `condition` is checked with the queue locked and interrupts masked, so
+    /// it cannot miss a wakeup from code that changes the condition before
+    /// calling [`WaitQueue::wake_one`] or [`WaitQueue::wake_all`]. Wakeups may
+    /// be spurious, in which case `condition` is checked again.
+    pub fn wait_until<T>(&self, mut condition: impl FnMut() -> Option<T>) -> T {
+        loop {
+            // Stay masked until switched away, after the queue is unlocked
+            let mask = interrupt::disable();
+            let mut waiters = self.waiters.lock();
+
+            if let Some(value) = condition() {
+                drop(waiters);
+                interrupt::restore(mask);
+                return value;
+            }
+
+            waiters.push_back(thread::prepare_block());
+            drop(waiters);
+
+            thread::block();
+            interrupt::restore(mask);
+        }
+    }
+
+    /// Wake the longest waiting thread, returning whether there was one.
+    pub fn wake_one(&self) -> bool {
+        let waiter = self.waiters.lock().pop_front();
+        waiter.map(thread::wake).is_some()
+    }
+
+    /// Wake every waiting thread, returning how many there were.
+    pub fn wake_all(&self) -> usize {
+        let waiters = core::mem::take(&mut *self.waiters.lock());
+        let count = waiters.len();
+        waiters.into_iter().for_each(thread::wake);
+        count
+    }
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,260 @@
+//! System calls, made from EL0 with `svc #0` following the ABI in
+//! [`rosin_abi`].
+
+use alloc::string::String;
+use alloc::vec;
+use core::time::Duration;
+
+pub use rosin_abi::Error;
+pub use rosin_abi::Number;
+pub use rosin_abi::fd;
+pub use rosin_abi::protection;
+
+use crate::fs;
+use crate::interrupt::Frame;
+use crate::mem::PAGE_SIZE;
+use crate::mem::User;
+use crate::mem::Virt;
+use crate::mmu;
+use crate::process;
+use crate::process::Process;
+use crate::thread;
+
+impl From<process::Error> for Error {
+    fn from(error: process::Error) -> Self {
+        match error {
+            process::Error::OutOfMemory | process::Error::TooManyProcesses => Error::NoMemory,
+            process::Error::InvalidAddress | process::Error::AlreadyMapped => Error::Invalid,
+            process::Error::InvalidExecutable => Error::NoExec,
+        }
+    }
+}
+
+impl From<fs::Error> for Error {
+    fn from(error: fs::Error) -> Self {
+        match error {
+            fs::Error::NotFound => Error::NoEntry,
+            fs::Error::Exists => Error::Exists,
+            fs::Error::NotDirectory => Error::NotDirectory,
+            fs::Error::IsDirectory => Error::IsDirectory,
+            fs::Error::Invalid => Error::Invalid,
+            fs::Error::NameTooLong => Error::NameTooLong,
+            fs::Error::BadFd => Error::BadFd,
+            fs::Error::TooManyFiles => Error::TooManyFiles,
+            fs::Error::NoSpace => Error::NoSpace,
+        }
+    }
+}
+
+type Handler = fn(&Process, [u64; 6]) -> Result<u64, Error>;
+
+/// Handlers indexed by [`Number`].
+const TABLE: [Handler; 14] = [
+    read, write, exit, yield_, sleep, getpid, mmap, munmap, open, close, lseek, stat, readdir,
+    mkdir,
+];
+
+/// Handle the system call in `frame`, made by the current process.
+pub(crate) fn dispatch(frame: &mut Frame) {
+    let process = process::current().expect("System call outside of a process");
+    let args = [
+        frame.x[0], frame.x[1], frame.x[2], frame.x[3], frame.x[4], frame.x[5],
+    ];
+
+    let result = match TABLE.get(frame.x[8] as usize) {
+        Some(handler) => handler(&process, args),
+        None => Err(Error::NoSys),
+    };
+
+    frame.x[0] = rosin_abi::encode(result);
+}
+
+/// Copy `bytes.len()` bytes from readable user memory at `virt`.
+pub fn copy_from_user(bytes: &mut [u8], virt: u64) -> Result<(), Error> {
+    let process = process::current().ok_or(Error::Fault)?;
+    process
+        .copy_from_user(user(&process, virt)?, bytes)
+        .map_err(|_| Error::Fault)
+}
+
+/// Copy `bytes` to writable user memory at `virt`.
+pub fn copy_to_user(virt: u64, bytes: &[u8]) -> Result<(), Error> {
+    let process = process::current().ok_or(Error::Fault)?;
+    process
+        .copy_to_user(user(&process, virt)?, bytes)
+        .map_err(|_| Error::Fault)
+}
+
+/// Validate a user address against `process`'s address space.
+fn user(process: &Process, virt: u64) -> Result<Virt<User>, Error> {
+    match virt < process.limit() {
+        true => Ok(Virt::new(virt)),
+        false => Err(Error::Fault),
+    }
+}
+
+/// Most bytes transferred by one [`read`], or copied through the kernel
+/// at a time by [`write`].
+const CHUNK: usize = 1 << 12;
+
+/// Longest path accepted, in bytes.
+const PATH_MAX: u64 = 4096;
+
+fn read(process: &Process, [fd, buffer, len, ..]: [u64; 6]) -> Result<u64, Error> {
+    let file = process.files().get(fd)?;
+    let mut bytes = vec![0u8; (len as usize).min(CHUNK)];
+
+    // Input is consumed and the offset advanced by reading, so fail first
+    process
+        .check_user_writable(user(process, buffer)?, bytes.len())
+        .map_err(|_| Error::Fault)?;
+
+    let read = file.read(&mut bytes)?;
+    copy_to_user(buffer, &bytes[..read])?;
+    Ok(read as u64)
+}
+
+fn write(process: &Process, [fd, buffer, len, ..]: [u64; 6]) -> Result<u64, Error> {
+    let file = process.files().get(fd)?;
+    let mut bytes = vec![0u8; (len as usize).min(CHUNK)];
+    let mut written = 0;
+    while written < len {
+        let chunk = ((len - written) as usize).min(CHUNK);
+        copy_from_user(&mut bytes[..chunk], buffer.wrapping_add(written))?;
+
+        let wrote = file.write(&bytes[..chunk])?;
+        written += wrote as u64;
+        if wrote < chunk {
+            break;
+        }
+    }
+
+    Ok(written)
+}
+
+fn exit(process: &Process, [code, ..]: [u64; 6]) -> Result<u64, Error> {
+    process.exit(process::Status::Exited(code as i32));
+    thread::exit()
+}
+
+fn yield_(_: &Process, _: [u64; 6]) -> Result<u64, Error> {
+    thread::yield_now();
+    Ok(0)
+}
+
+fn sleep(_: &Process, [nanoseconds, ..]: [u64; 6]) -> Result<u64, Error> {
+    thread::sleep(Duration::from_nanos(nanoseconds));
+    Ok(0)
+}
+
+fn getpid(process: &Process, _: [u64; 6]) -> Result<u64, Error> {
+    Ok(process.id().value())
+}
+
+fn mmap(process: &Process, [address, len, protection, ..]: [u64; 6]) -> Result<u64, Error> {
+    let attr = mmu::Attr::user(
+        protection & protection::READ != 0,
+        protection & protection::WRITE != 0,
+        protection & protection::EXECUTE != 0,
+    );
+
+    if attr.is_writable_executable() {
+        return Err(Error::Invalid);
+    }
+
+    let len = pages(len)?;
+    match address {
+        0 => Ok(u64::from(process.map_anywhere(len, attr)?)),
+        _ => {
+            process.map(
+                user(process, address).map_err(|_| Error::Invalid)?,
+                len,
+                attr,
+            )?;
+            Ok(address)
+        }
+    }
+}
+
+fn munmap(process: &Process, [address, len, ..]: [u64; 6]) -> Result<u64, Error> {
+    let virt = user(process, address).map_err(|_| Error::Invalid)?;
+    process.unmap(virt, pages(len)?)?;
+    Ok(0)
+}
+
+/// Round a non-zero `len` from user space up to whole pages.
+fn pages(len: u64) -> Result<u64, Error> {
+    match len {
+        0 => Err(Error::Invalid),
+        _ => len
+            .checked_next_multiple_of(PAGE_SIZE)
+            .ok_or(Error::Invalid),
+    }
+}
+
+/// Copy a UTF-8 path of `len` bytes from user memory at `pointer`.
+fn path(pointer: u64, len: u64) -> Result<String, Error> {
+    if len > PATH_MAX {
+        return Err(Error::NameTooLong);
+    }
+
+    let mut bytes = vec![0u8; len as usize];
+    copy_from_user(&mut bytes, pointer)?;
+    String::from_utf8(bytes).map_err(|_| Error::Invalid)
+}
+
+/// Copy a `#[repr(C)]` value without padding to user memory at `virt`.
+fn copy_value_to_user<T: Copy>(virt: u64, value: &T) -> Result<(), Error> {
+    let bytes =
+        unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) };
+    copy_to_user(virt, bytes)
+}
+
+fn open(process: &Process, [pointer, len, flags, ..]: [u64; 6]) -> Result<u64, Error> {
+    let file = fs::open(&path(pointer, len)?, flags)?;
+    Ok(process.files().insert(file)?)
+}
+
+fn close(process: &Process, [fd, ..]: [u64; 6]) -> Result<u64, Error> {
+    process.files().remove(fd)?;
+    Ok(0)
+}
+
+fn lseek(process: &Process, [fd, offset, whence, ..]: [u64; 6]) -> Result<u64, Error> {
+    let file = process.files().get(fd)?;
+    Ok(file.seek(offset as i64, whence)?)
+}
+
+fn stat(_: &Process, [pointer, len, stat, ..]: [u64; 6]) -> Result<u64, Error> {
+    copy_value_to_user(stat, &fs::stat(&path(pointer, len)?)?)?;
+    Ok(0)
+}
+
+fn readdir(process: &Process, [fd, entry, ..]: [u64; 6]) -> Result<u64, Error> {
+    let file = process.files().get(fd)?;
+
+    // Like `read`, fail before advancing past the entry
+    process
+        .check_user_writable(user(process, entry)?, size_of::<rosin_abi::DirEntry>())
+        .map_err(|_| Error::Fault)?;
+
+    let Some(next) = file.read_dir()? else {
+        return Ok(0);
+    };
+
+    let mut dir_entry = rosin_abi::DirEntry {
+        ino: next.ino,
+        mode: next.mode,
+        len: next.name.len() as u32,
+        ..Default::default()
+    };
+    dir_entry.name[..next.name.len()].copy_from_slice(next.name.as_bytes());
+
+    copy_value_to_user(entry, &dir_entry)?;
+    Ok(1)
+}
+
+fn mkdir(_: &Process, [pointer, len, ..]: [u64; 6]) -> Result<u64, Error> {
+    fs::mkdir(&path(pointer, len)?)?;
+    Ok(0)
+}
@@ -0,0 +1,722 @@
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::sync::Weak;
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::fmt::Debug;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+use core::time::Duration;
+
+use crate::bitset;
+use crate::interrupt;
+use crate::mem::stack::Stack;
+use crate::mmu;
+use crate::process::Process;
+use crate::smp;
+use crate::smp::CPUS;
+use crate::smp::PerCpu;
+use crate::smp::cpu_id;
+use crate::sync::IrqSpinLock;
+use crate::sync::Once;
+use crate::sync::SpinLock;
+use crate::sync::WaitQueue;
+use crate::time;
+use crate::time::Instant;
+
+/// Time a thread runs before it is preempted by the next ready thread.
+pub const SLICE: Duration = Duration::from_millis(10);
+
+/// Number of time slices between attempts to balance load across cores.
+const BALANCE: u64 = 10;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Id(u64);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum State {
+    Ready,
+    Running,
+    /// Waiting in a [`WaitQueue`] until woken
+    Blocked,
+    Dead,
+}
+
+/// Kernel thread, which runs at EL1 on its own kernel stack, and for user
+/// threads, at EL0 in its process.
+pub struct Thread {
+    id: Id,
+    state: AtomicU8,
+    context: UnsafeCell<Context>,
+    /// Function to run on first switch, taken by the thread itself
+    entry: UnsafeCell<Option<Box<dyn FnOnce() + Send>>>,
+    /// Freed with the thread. `None` for threads that adopted an existing
+    /// stack (see [`current`]).
+    _stack: Option<Stack>,
+    /// Cycles spent running, as of the last switch away from the thread
+    runtime: AtomicU64,
+    /// Number of times the thread was switched to
+    switches: AtomicU64,
+    /// Cores the thread may run on
+    affinity: bitset::Sized<1>,
+    /// Core the thread last ran on
+    cpu: AtomicUsize,
+    /// Whether a core is running the thread or has yet to save its context
+    on_cpu: AtomicBool,
+    /// Threads waiting for this one to exit
+    joiners: WaitQueue,
+    /// Address space installed while the thread runs, if it is a user thread
+    process: Option<Arc<Process>>,
+}
+
+// The context is only accessed while switching to or from the thread, and the
+// entry only by the thread itself, so neither is shared.
+unsafe impl Sync for Thread {}
+unsafe impl Send for Thread {}
+
+impl Thread {
+    fn new(
+        entry: Option<Box<dyn FnOnce() + Send>>,
+        stack: Option<Stack>,
+        process: Option<Arc<Process>>,
+    ) -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+
+        let mut context = Context::default();
+        if let Some(stack) = &stack {
+            context.sp = u64::from(stack.top());
+            context.lr = start as usize as u64;
+        }
+
+        Self {
+            id: Id(NEXT.fetch_add(1, Ordering::Relaxed)),
+            state: AtomicU8::new(State::Ready as u8),
+            context: UnsafeCell::new(context),
+            entry: UnsafeCell::new(entry),
+            _stack: stack,
+            runtime: AtomicU64::new(0),
+            switches: AtomicU64::new(0),
+            affinity: {
+                let affinity = bitset::Sized::new();
+                (0..CPUS).for_each(|cpu| _ = affinity.set(cpu));
+                affinity
+            },
+            cpu: AtomicUsize::new(cpu_id()),
+            on_cpu: AtomicBool::new(false),
+            joiners: WaitQueue::new(),
+            process,
+        }
+    }
+
+    /// Make the thread visible to [`stats`].
+    fn register(self) -> Arc<Self> {
+        let thread = Arc::new(self);
+        let mut threads = THREADS.lock();
+        threads.retain(|thread| thread.strong_count() > 0);
+        threads.push(Arc::downgrade(&thread));
+        thread
+    }
+
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    pub fn state(&self) -> State {
+        match self.state.load(Ordering::Acquire) {
+            0 => State::Ready,
+            1 => State::Running,
+            2 => State::Blocked,
+            _ => State::Dead,
+        }
+    }
+
+    fn set_state(&self, state: State) {
+        self.state.store(state as u8, Ordering::Release);
+    }
+
+    /// Time spent running, as of the last switch away from the thread.
+    pub fn runtime(&self) -> Duration {
+        Duration::from(time::Cycle::new(self.runtime.load(Ordering::Relaxed)))
+    }
+
+    pub fn switches(&self) -> u64 {
+        self.switches.load(Ordering::Relaxed)
+    }
+
+    pub fn process(&self) -> Option<&Arc<Process>> {
+        self.process.as_ref()
+    }
+
+    /// Core the thread is running on, or last ran on.
+    pub fn cpu(&self) -> usize {
+        self.cpu.load(Ordering::Relaxed)
+    }
+
+    /// Cores the thread may run on, all of them by default.
+    pub fn affinity(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..CPUS).filter(|cpu| self.affinity.get(*cpu))
+    }
+
+    /// Restrict the thread to `cpus`. Takes effect the next time the thread
+    /// is scheduled, so a running thread may finish its time slice elsewhere.
+    pub fn set_affinity(&self, cpus: impl IntoIterator<Item = usize>) {
+        let mut mask = 0u64;
+        for cpu in cpus {
+            assert!(cpu < CPUS, "Invalid CPU {cpu}");
+            mask |= 1 << cpu;
+        }
+        assert!(mask != 0, "Empty affinity mask");
+
+        (0..CPUS).for_each(|cpu| match mask & (1 << cpu) {
+            0 => _ = self.affinity.unset(cpu),
+            _ => _ = self.affinity.set(cpu),
+        });
+    }
+
+    fn allows(&self, cpu: usize) -> bool {
+        self.affinity.get(cpu)
+    }
+}
+
+impl Debug for Thread {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Thread")
+            .field("id", &self.id)
+            .field("state", &self.state())
+            .field("runtime", &self.runtime())
+            .field("switches", &self.switches())
+            .field("cpu", &self.cpu())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Registers preserved across `_switch_context` by the AAPCS64 calling convention.
+#[repr(C)]
+#[derive(Default)]
+struct Context {
+    x19_x28: [u64; 10],
+    fp: u64,
+    lr: u64,
+    sp: u64,
+}
+
+/// Threads ready to run on each core.
+static READY: PerCpu<IrqSpinLock<VecDeque<Arc<Thread>>>> =
+    PerCpu::new([const { IrqSpinLock::new(VecDeque::new()) }; CPUS]);
+
+/// Whether each core is running its idle thread, and needs an
+/// inter-processor interrupt to notice new threads before its next tick.
+static IDLING: PerCpu<AtomicBool> = PerCpu::new([const { AtomicBool::new(false) }; CPUS]);
+
+/// Every thread that has not been freed, for [`stats`].
+static THREADS: IrqSpinLock<Vec<Weak<Thread>>> = IrqSpinLock::new(Vec::new());
+
+/// Total number of context switches across all cores.
+static SWITCHES: AtomicU64 = AtomicU64::new(0);
+
+/// Total number of threads moved between cores by [`balance`].
+static MIGRATIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Threads blocked in [`sleep`], with the time to wake them.
+static SLEEPING: IrqSpinLock<Vec<(Instant, Arc<Thread>)>> = IrqSpinLock::new(Vec::new());
+
+/// Thread running on each core, and the thread it most recently switched
+/// away from, which the new thread requeues once its context is saved.
+///
+/// Only accessed by its own core with interrupts masked.
+struct Local {
+    current: UnsafeCell<Option<Arc<Thread>>>,
+    previous: UnsafeCell<Option<(Arc<Thread>, bool)>>,
+    /// When the current thread was switched to
+    since: UnsafeCell<Option<Instant>>,
+    /// Set in interrupt context to switch threads on the way out
+    reschedule: AtomicBool,
+    /// Timer interrupts taken, for scheduling [`balance`]
+    ticks: AtomicU64,
+}
+
+unsafe impl Sync for Local {}
+
+static LOCAL: PerCpu<Local> = PerCpu::new(
+    [const {
+        Local {
+            current: UnsafeCell::new(None),
+            previous: UnsafeCell::new(None),
+            since: UnsafeCell::new(None),
+            reschedule: AtomicBool::new(false),
+            ticks: AtomicU64::new(0),
+        }
+    }; CPUS],
+);
+
+/// Thread run by each core when no other thread is ready, which is never
+/// queued in [`READY`] and never leaves its core.
+static IDLE: PerCpu<Once<Arc<Thread>>> = PerCpu::new([const { Once::new() }; CPUS]);
+
+pub struct JoinHandle<T> {
+    thread: Arc<Thread>,
+    result: Arc<SpinLock<Option<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn thread(&self) -> &Arc<Thread> {
+        &self.thread
+    }
+
+    /// Block until the thread exits.
+    pub fn join(self) -> T {
+        self.thread
+            .joiners
+            .wait_until(|| (self.thread.state() == State::Dead).then_some(()));
+
+        self.result
+            .lock()
+            .take()
+            .expect("Thread exited without a result")
+    }
+}
+
+/// Run `f` on a new thread with its own kernel [`Stack`].
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    spawn_in(None, f)
+}
+
+/// Like [`spawn`], but running with `process`'s address space installed.
+pub(crate) fn spawn_in<F, T>(process: Option<Arc<Process>>, f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let result = Arc::new(SpinLock::new(None));
+    let entry = {
+        let result = result.clone();
+        Box::new(move || *result.lock() = Some(f()))
+    };
+
+    let stack = Stack::new().expect("Failed to allocate thread stack");
+    let thread = Thread::new(Some(entry), Some(stack), process).register();
+    enqueue(thread.clone());
+
+    JoinHandle { thread, result }
+}
+
+/// The thread running on the current core. The first call on each core
+/// adopts the running context as a thread.
+pub fn current() -> Arc<Thread> {
+    let mask = interrupt::disable();
+    let current = unsafe { &mut *LOCAL.get().current.get() }
+        .get_or_insert_with(|| {
+            let thread = Thread::new(None, None, None);
+            thread.set_state(State::Running);
+            thread.on_cpu.store(true, Ordering::Relaxed);
+            thread.register()
+        })
+        .clone();
+    interrupt::restore(mask);
+    current
+}
+
+/// Give up the current core to the next ready thread, if any.
+pub fn yield_now() {
+    schedule(true);
+}
+
+/// Block the current thread for at least `duration`, rounded up to the
+/// next timer tick (see [`SLICE`]).
+pub fn sleep(duration: Duration) {
+    let deadline = Instant::now() + duration;
+
+    // Stay masked until switched away, after the list is unlocked
+    let mask = interrupt::disable();
+    SLEEPING.lock().push((deadline, prepare_block()));
+    block();
+    interrupt::restore(mask);
+}
+
+/// Wake threads whose [`sleep`] has elapsed.
+fn wake_sleeping() {
+    let now = Instant::now();
+    let mut woken = Vec::new();
+
+    SLEEPING
+        .lock()
+        .retain(|(deadline, thread)| match *deadline <= now {
+            true => {
+                woken.push(thread.clone());
+                false
+            }
+            false => true,
+        });
+
+    woken.into_iter().for_each(wake);
+}
+
+/// Terminate the current thread.
+pub fn exit() -> ! {
+    let thread = current();
+    thread.set_state(State::Dead);
+    thread.joiners.wake_all();
+    drop(thread);
+
+    schedule(false);
+    unreachable!("Exited thread was scheduled")
+}
+
+/// Switch away from the current thread, which the caller has marked as
+/// blocked (see [`prepare_block`]), until it is passed to [`wake`].
+///
+/// Must be called with interrupts masked since [`prepare_block`], so that
+/// nothing on this core can observe the thread as blocked while it runs.
+pub(crate) fn block() {
+    assert!(!is_idle(&current()), "Idle thread cannot block");
+    schedule(false);
+}
+
+/// Mark the current thread as blocked ahead of [`block`], returning it for
+/// the caller to record where its waker will find it.
+pub(crate) fn prepare_block() -> Arc<Thread> {
+    let thread = current();
+    thread.set_state(State::Blocked);
+    thread
+}
+
+/// Make a blocked thread ready to run. Has no effect if the thread was
+/// already woken.
+pub(crate) fn wake(thread: Arc<Thread>) {
+    if thread
+        .state
+        .compare_exchange(
+            State::Blocked as u8,
+            State::Ready as u8,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        )
+        .is_err()
+    {
+        return;
+    }
+
+    // The thread may have been woken before it switched away, and must not
+    // run elsewhere until its context is saved
+    while thread.on_cpu.load(Ordering::Acquire) {
+        crate::pause();
+    }
+
+    enqueue(thread);
+}
+
+/// Turn the current context into this core's idle thread, which waits for
+/// interrupts whenever no other thread is ready.
+pub fn idle() -> ! {
+    let thread = current();
+    let idle = IDLE.get().call_once(|| thread.clone());
+    assert!(Arc::ptr_eq(idle, &thread), "CPU already has an idle thread");
+    drop(thread);
+
+    IDLING.get().store(true, Ordering::SeqCst);
+
+    enable_preemption();
+    run_idle()
+}
+
+fn run_idle() -> ! {
+    loop {
+        yield_now();
+        interrupt::wait();
+    }
+}
+
+/// This core's idle thread, created on first use if the core has none.
+fn idle_thread() -> Arc<Thread> {
+    IDLE.get()
+        .call_once(|| {
+            let stack = Stack::new().expect("Failed to allocate idle stack");
+            Thread::new(Some(Box::new(|| run_idle())), Some(stack), None).register()
+        })
+        .clone()
+}
+
+fn is_idle(thread: &Arc<Thread>) -> bool {
+    IDLE.get()
+        .get()
+        .is_some_and(|idle| Arc::ptr_eq(idle, thread))
+}
+
+/// Preempt threads on the current core every [`SLICE`], and unmask
+/// interrupts so that the timer can fire.
+pub fn enable_preemption() {
+    interrupt::enable_timer(SLICE);
+    interrupt::enable();
+}
+
+/// Handle the timer interrupt: start the next time slice, and switch threads
+/// once the interrupt handler returns.
+pub(crate) fn tick() {
+    interrupt::enable_timer(SLICE);
+    wake_sleeping();
+
+    if LOCAL.get().ticks.fetch_add(1, Ordering::Relaxed) % BALANCE == 0 {
+        balance();
+    }
+
+    reschedule();
+}
+
+/// Switch threads on the current core once the interrupt handler returns.
+pub(crate) fn reschedule() {
+    LOCAL.get().reschedule.store(true, Ordering::Relaxed);
+}
+
+/// Called with interrupts masked on the way out of an interrupt handler,
+/// which resumes once the interrupted thread is switched back to.
+pub(crate) fn irq_exit() {
+    if LOCAL.get().reschedule.swap(false, Ordering::Relaxed) {
+        schedule(true);
+    }
+}
+
+/// Switch to the next ready thread, requeuing the current one if `requeue`.
+///
+/// If no thread is ready, the current thread keeps running if `requeue`,
+/// and otherwise the core switches to its idle thread.
+fn schedule(requeue: bool) {
+    let prev = current();
+    let mask = interrupt::disable();
+    let idle = is_idle(&prev);
+
+    let next = dequeue();
+    let next = match next {
+        Some(next) => next,
+        None if requeue || idle => {
+            interrupt::restore(mask);
+            return;
+        }
+        None => idle_thread(),
+    };
+
+    // The idle thread is switched to directly when needed
+    let requeue = requeue && !idle;
+
+    if prev.state() == State::Running {
+        prev.set_state(State::Ready);
+    }
+    next.set_state(State::Running);
+    next.cpu.store(cpu_id(), Ordering::Relaxed);
+    next.on_cpu.store(true, Ordering::Relaxed);
+    IDLING.get().store(is_idle(&next), Ordering::SeqCst);
+
+    let local = LOCAL.get();
+    let now = Instant::now();
+    if let Some(since) = unsafe { (*local.since.get()).replace(now.clone()) } {
+        prev.runtime
+            .fetch_add((now - since).value(), Ordering::Relaxed);
+    }
+    next.switches.fetch_add(1, Ordering::Relaxed);
+    SWITCHES.fetch_add(1, Ordering::Relaxed);
+
+    match &next.process {
+        Some(process) => process.activate(),
+        None if prev.process.is_some() => mmu::deactivate_user(),
+        None => (),
+    }
+
+    let (from, to) = (prev.context.get(), next.context.get());
+    unsafe {
+        *local.current.get() = Some(next);
+        *local.previous.get() = Some((prev, requeue));
+        _switch_context(from, to);
+    }
+
+    finish_switch();
+    interrupt::restore(mask);
+}
+
+/// Requeue or release the thread this core just switched away from, now that
+/// its context is saved.
+fn finish_switch() {
+    let previous = unsafe { (*LOCAL.get().previous.get()).take() };
+    if let Some((thread, _)) = &previous {
+        thread.on_cpu.store(false, Ordering::Release);
+    }
+
+    if let Some((thread, true)) = previous {
+        match thread.allows(cpu_id()) {
+            true => READY.get().lock().push_back(thread),
+            false => enqueue(thread),
+        }
+    }
+}
+
+/// Queue `thread` on the least loaded core it may run on, waking that core
+/// if it is idle.
+fn enqueue(thread: Arc<Thread>) {
+    let id = cpu_id();
+    let load = |cpu: usize| {
+        let busy = !IDLING.of(cpu).load(Ordering::SeqCst);
+        READY.of(cpu).lock().len() + busy as usize
+    };
+
+    let cpu = smp::online()
+        .filter(|cpu| thread.allows(*cpu))
+        .min_by_key(|cpu| (load(*cpu), *cpu != id))
+        .expect("No online CPU in thread affinity");
+
+    READY.of(cpu).lock().push_back(thread);
+
+    if cpu != id && IDLING.of(cpu).load(Ordering::SeqCst) {
+        smp::send_ipi(cpu, smp::Message::Reschedule);
+    }
+}
+
+/// Pop the next thread from the current core's queue, forwarding threads
+/// whose affinity no longer allows the current core.
+fn dequeue() -> Option<Arc<Thread>> {
+    let id = cpu_id();
+    loop {
+        let thread = READY.get().lock().pop_front()?;
+        match thread.allows(id) {
+            true => return Some(thread),
+            false => enqueue(thread),
+        }
+    }
+}
+
+/// Pull a thread to the current core from the busiest core, if that core has
+/// at least two more threads waiting.
+fn balance() {
+    let id = cpu_id();
+    let local = READY.get().lock().len();
+
+    let Some((busiest, waiting)) = smp::online()
+        .filter(|cpu| *cpu != id)
+        .map(|cpu| (cpu, READY.of(cpu).lock().len()))
+        .max_by_key(|(_, waiting)| *waiting)
+    else {
+        return;
+    };
+
+    if waiting < local + 2 {
+        return;
+    }
+
+    // Take from the back, where threads have waited the least
+    let thread = {
+        let mut queue = READY.of(busiest).lock();
+        queue
+            .iter()
+            .rposition(|thread| thread.allows(id))
+            .and_then(|index| queue.remove(index))
+    };
+
+    if let Some(thread) = thread {
+        READY.get().lock().push_back(thread);
+        MIGRATIONS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Scheduling statistics, with runtimes as of each thread's last switch.
+#[derive(Debug)]
+pub struct Stats {
+    pub switches: u64,
+    pub migrations: u64,
+    /// Threads waiting in each core's queue
+    pub ready: [usize; CPUS],
+    pub threads: Vec<ThreadStats>,
+}
+
+#[derive(Debug)]
+pub struct ThreadStats {
+    pub id: Id,
+    pub state: State,
+    pub idle: bool,
+    pub runtime: Duration,
+    pub switches: u64,
+    pub cpu: usize,
+}
+
+pub fn stats() -> Stats {
+    let threads = THREADS
+        .lock()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect::<Vec<_>>();
+
+    let threads = threads
+        .iter()
+        .map(|thread| ThreadStats {
+            id: thread.id(),
+            state: thread.state(),
+            idle: IDLE
+                .iter()
+                .filter_map(Once::get)
+                .any(|idle| Arc::ptr_eq(idle, thread)),
+            runtime: thread.runtime(),
+            switches: thread.switches(),
+            cpu: thread.cpu(),
+        })
+        .collect();
+
+    Stats {
+        switches: SWITCHES.load(Ordering::Relaxed),
+        migrations: MIGRATIONS.load(Ordering::Relaxed),
+        ready: core::array::from_fn(|cpu| READY.of(cpu).lock().len()),
+        threads,
+    }
+}
+
+/// Entry point of new threads, switched to from [`schedule`].
+extern "C" fn start() -> ! {
+    finish_switch();
+    interrupt::enable();
+
+    let thread = current();
+    let entry = unsafe { (*thread.entry.get()).take() }.expect("Thread started twice");
+    drop(thread);
+
+    entry();
+    exit()
+}
+
+unsafe extern "C" {
+    /// Save callee-saved registers to `from` and restore them from `to`,
+    /// returning into the thread that last saved `to`.
+    fn _switch_context(from: *mut Context, to: *const Context);
+}
+
+core::arch::global_asm! {
+r"
+.pushsection .text
+.global _switch_context
+_switch_context:
+    stp x19, x20, [x0, 0]
+    stp x21, x22, [x0, 16]
+    stp x23, x24, [x0, 32]
+    stp x25, x26, [x0, 48]
+    stp x27, x28, [x0, 64]
+    stp x29, x30, [x0, 80]
+    mov x9, sp
+    str x9, [x0, 96]
+
+    ldp x19, x20, [x1, 0]
+    ldp x21, x22, [x1, 16]
+    ldp x23, x24, [x1, 32]
+    ldp x25, x26, [x1, 48]
+    ldp x27, x28, [x1, 64]
+    ldp x29, x30, [x1, 80]
+    ldr x9, [x1, 96]
+    mov sp, x9
+    ret
+.size _switch_context, . - _switch_context
+.type _switch_context, %function
+.popsection
+"
+}
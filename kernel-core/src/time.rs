@@ -1,4 +1,5 @@
 use core::ops::Add;
+use core::ops::Sub;
 use core::time::Duration;
 
 use aarch64_cpu::asm;
@@ -31,6 +32,13 @@ impl Add<Cycle> for Instant {
     }
 }
 
+impl Sub for Instant {
+    type Output = Cycle;
+    fn sub(self, rhs: Self) -> Self::Output {
+        Cycle(self.0.0.saturating_sub(rhs.0.0))
+    }
+}
+
 impl Instant {
     pub fn now() -> Self {
         asm::barrier::isb(asm::barrier::SY);
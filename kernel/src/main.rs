@@ -4,169 +4,123 @@
 use core::ffi;
 use core::panic::PanicInfo;
 use core::ptr::NonNull;
-use core::time::Duration;
 
-use kernel_core::device;
+use kernel_core::fs;
 use kernel_core::info;
+use kernel_core::mem::Kernel;
 use kernel_core::mem::Phys;
 use kernel_core::mem::Virt;
-use kernel_core::mem::page;
-use kernel_core::print;
-use kernel_core::println;
-use kernel_core::time;
-
-core::arch::global_asm! {
-r"
-.pushsection .text.boot
-
-_start:
-    mrs x4, SCTLR_EL1
-
-    # M: MMU enable
-    orr x4, x4, (1 << 0)
-    # C: Cacheability for data accesses
-    orr x4, x4, (1 << 2)
-    # I: Cacheability for instruction accesses
-    orr x4, x4, (1 << 12)
-    # WXN: Disable write XOR execute
-    and x4, x4, ~(1 << 19)
-
-    isb sy
-    msr SCTLR_EL1, x4
-    isb sy
-
-    ldr x4, =__STACK_HI
-    mov sp, x4
-    ldr x4, =_start_kernel
-    br x4
-
-.size _start, . - _start
-.type _start, %function
-.global _start
-.popsection
-"
-}
+use kernel_core::mem::stack::Stack;
+use kernel_core::process::Process;
+use kernel_core::thread;
+use kernel_core::warn;
+
+kernel_core::entry!();
 
 unsafe extern "C" {
     static __KERNEL_LO: ffi::c_void;
     static __KERNEL_HI: ffi::c_void;
     static __KERNEL_OFFSET: ffi::c_void;
+    static __STACK_GUARD: ffi::c_void;
+    static __STACK_LO: ffi::c_void;
 }
 
 #[unsafe(link_section = ".text.start")]
 #[unsafe(no_mangle)]
 unsafe extern "C" fn _start_kernel(
     device_tree: NonNull<device_tree::blob::Header>,
-    page_table: &mut kernel_core::mmu::PageTable<kernel_core::mem::Kernel>,
-    heap: *mut u8,
+    tables_lo: u64,
+    tables_hi: u64,
+    initrd_lo: u64,
+    initrd_hi: u64,
 ) -> ! {
     kernel_core::init();
 
     info!("Hello, world!");
 
-    let device_tree = unsafe { device_tree::Blob::from_ptr(device_tree) };
+    let device_tree = unsafe { device_tree::Blob::from_ptr(device_tree.cast()) };
 
     info!("Device tree header: {:#x?}", device_tree.header());
 
-    let root = device_tree.root();
-
-    const PAGE_SIZE: usize = 1 << 16;
-
-    let ranges = [
-        (
-            device_tree.as_ptr().as_ptr() as usize,
-            device_tree.header().len(),
-        ),
-        (
-            page_table as *mut _ as usize,
-            core::mem::size_of_val(page_table),
-        ),
-        unsafe {
-            (
-                (&__KERNEL_OFFSET as *const _ as usize),
-                (&__KERNEL_HI as *const _ as usize) - (&__KERNEL_OFFSET as *const _ as usize),
-            )
-        },
-    ];
-
-    let total = ((1 << 30)
-        - ranges
-            .into_iter()
-            .map(|(_, len)| len.next_multiple_of(PAGE_SIZE))
-            .sum::<usize>())
-        / PAGE_SIZE
-        / 64;
-
-    let allocator =
-        unsafe { kernel_core::mem::alloc::Page::from_raw_parts_mut(heap.cast::<u64>(), total) };
-
-    let offset = unsafe { &__KERNEL_OFFSET } as *const _ as usize;
-
-    for page in (0..kernel_core::mem::alloc::Page::size_of(total))
-        .step_by(PAGE_SIZE)
-        .map(|page| heap as u64 + page as u64)
+    // Bootloader maps all of RAM at the kernel offset
+    unsafe { kernel_core::mmu::init_kernel() };
+
+    let virt = |address: *const ffi::c_void| Virt::<Kernel>::new(address as u64).to_phys();
+
+    kernel_core::mem::init(
+        &device_tree,
+        unsafe { virt(&__KERNEL_OFFSET)..virt(&__KERNEL_HI) },
+        Phys::new(tables_lo)..Phys::new(tables_hi),
+        Phys::new(initrd_lo)..Phys::new(initrd_hi),
+    );
+
+    kernel_core::initrd::init(Phys::new(initrd_lo)..Phys::new(initrd_hi));
+
+    // Device MMIO is accessed through the linear map from here on
+    unsafe { kernel_core::mmu::unmap_identity(kernel_core::mem::alloc::global()) };
+
+    #[cfg(feature = "pl011")]
     {
-        page_table.map(
-            Virt::new(page),
-            Phys::new(page - offset as u64),
-            kernel_core::mmu::Attr::Normal {
-                read: true,
-                write: true,
-                execute: false,
-            },
-        );
+        kernel_core::init_console_pl011(&Default::default());
+        info!("Switched console to PL011",);
     }
 
-    allocator.fill();
-    info!("Total pages: {:#x?}", allocator.len());
-    for (base, len) in ranges.into_iter().chain(core::iter::once((
-        heap as usize,
-        kernel_core::mem::alloc::Page::size_of(total),
-    ))) {
-        info!("Reserving region {:#x?}...", len);
-        let base = base - offset;
-
-        for offset in (0..len).step_by(PAGE_SIZE) {
-            let phys = Phys::new(base as u64 + offset as u64);
-            info!("Reserve {:#x?}", phys);
-            allocator.reserve(page::Id::from(phys));
-        }
+    match kernel_core::mmu::check_wx() {
+        0 => info!("No writable and executable mappings",),
+        violations => warn!("Found {} writable and executable mappings", violations),
     }
 
-    info!("Available pages: {:#x?}", allocator.len());
-
-    // info!(
-    //     "Resolution: {}ns, frequency: {}hz",
-    //     Duration::from(time::Cycle::ONE).as_nanos(),
-    //     time::frequency(),
-    // );
-    // kernel::info!(
-    //     "Interrupt in 1 second... EL: {}",
-    //     CurrentEL.read(CurrentEL::EL)
-    // );
-    //
-    // kernel::irq::enable();
-    // kernel::irq::enable_timer(Duration::from_secs(1));
-
-    // let device_tree = &dev::bcm2837b0::DTB;
-
-    for _ in 0..2 {
-        info!("Sleeping for 1s...",);
-        time::spin(Duration::from_secs(1));
-    }
+    // Secondaries allocate their idle threads on the heap
+    kernel_core::mem::heap::init();
 
-    println!("Echo:");
-    loop {
-        let byte = unsafe { device::bcm2837b0::mini::Uart::new(0x3F21_5000) }.read_byte();
-        print!("{}", byte as char);
+    kernel_core::smp::start(&device_tree, secondary);
+
+    for cpu in kernel_core::smp::online() {
+        kernel_core::smp::call(cpu, || {
+            info!("Hello from CPU {}", kernel_core::smp::cpu_id())
+        });
     }
+
+    let stack = |address: *const ffi::c_void| Virt::<Kernel>::new(address as u64);
+    kernel_core::mem::stack::guard(unsafe { stack(&__STACK_GUARD)..stack(&__STACK_LO) });
+
+    Stack::new()
+        .expect("Failed to allocate kernel stack")
+        .enter(main)
 }
 
-fn recurse(depth: usize, node: device_tree::blob::Node) {
-    info!("{:|<depth$}-{:?}", "", node, depth = depth);
-    for child in node.children() {
-        recurse(depth + 1, child);
+/// First user program. There is no shell yet, so this echoes console input
+/// until Ctrl-D.
+const INIT: &str = "/bin/echo";
+
+extern "C" fn main() -> ! {
+    kernel_core::mmu::kernel(|page_table| info!("Kernel {:?}", page_table));
+
+    thread::enable_preemption();
+
+    match kernel_core::initrd::archive() {
+        None => info!("No initrd",),
+        Some(archive) => info!("Initrd: {:#?}", archive),
     }
+
+    // Before any process can make file system calls
+    fs::init();
+
+    // Before init can read standard input
+    kernel_core::init_console_input();
+
+    let image = fs::read(INIT).expect("Failed to read init");
+    let init = Process::new().expect("Failed to create init process");
+    init.exec(&image, &[INIT.as_bytes()], &[])
+        .expect("Failed to exec init");
+    drop(image);
+
+    panic!("Init {:?}", init.wait());
+}
+
+extern "C" fn secondary() -> ! {
+    // Run threads and wait for inter-processor interrupts
+    thread::idle()
 }
 
 #[panic_handler]
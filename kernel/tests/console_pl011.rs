@@ -0,0 +1,93 @@
+#![no_std]
+#![no_main]
+
+use core::ffi;
+use core::fmt::Write as _;
+use core::panic::PanicInfo;
+use core::ptr::NonNull;
+
+use arrayvec::ArrayVec;
+use kernel_core::mem::Kernel;
+use kernel_core::mem::Phys;
+use kernel_core::mem::Virt;
+use kernel_core::mem::stack::Stack;
+use kernel_core::print;
+
+kernel_core::entry!();
+
+unsafe extern "C" {
+    static __KERNEL_HI: ffi::c_void;
+    static __KERNEL_OFFSET: ffi::c_void;
+}
+
+/// Fits in the PL011's 16-byte receive FIFO.
+const MARKER: &str = "pl011 loopback";
+
+#[unsafe(link_section = ".text.start")]
+#[unsafe(no_mangle)]
+unsafe extern "C" fn _start_kernel(
+    device_tree: NonNull<device_tree::blob::Header>,
+    tables_lo: u64,
+    tables_hi: u64,
+    initrd_lo: u64,
+    initrd_hi: u64,
+) -> ! {
+    kernel_core::init();
+
+    let device_tree = unsafe { device_tree::Blob::from_ptr(device_tree.cast()) };
+
+    unsafe { kernel_core::mmu::init_kernel() };
+
+    let virt = |address: *const ffi::c_void| Virt::<Kernel>::new(address as u64).to_phys();
+
+    kernel_core::mem::init(
+        &device_tree,
+        unsafe { virt(&__KERNEL_OFFSET)..virt(&__KERNEL_HI) },
+        Phys::new(tables_lo)..Phys::new(tables_hi),
+        Phys::new(initrd_lo)..Phys::new(initrd_hi),
+    );
+
+    print!("console_pl011::loopback...\t");
+
+    // Query the UART clock from a thread stack, outside the linear map
+    Stack::new()
+        .expect("Failed to allocate kernel stack")
+        .enter(main)
+}
+
+extern "C" fn main() -> ! {
+    kernel_core::init_console_pl011(&Default::default());
+
+    kernel_core::UART.lock().set_loopback(true);
+    print!("{}", MARKER);
+
+    let mut received = ArrayVec::<u8, 16>::new();
+    {
+        let mut uart = kernel_core::UART.lock();
+        uart.flush();
+        while let Some(byte) = uart.try_read_byte() {
+            if received.try_push(byte).is_err() {
+                break;
+            }
+        }
+        uart.set_loopback(false);
+    }
+
+    // Report on the mini UART, where the header was written
+    let mut uart = kernel_core::UART_MINI.lock();
+    match received.as_slice() == MARKER.as_bytes() {
+        true => {
+            let _ = writeln!(uart, "[ok]");
+            kernel_core::spin()
+        }
+        false => {
+            let _ = writeln!(uart, "[failed]");
+            panic!("Console output did not reach the PL011: {:?}", received);
+        }
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kernel_core::handle_panic(info)
+}
@@ -0,0 +1,81 @@
+#![no_std]
+#![no_main]
+
+use core::ffi;
+use core::fmt::Write as _;
+use core::hint;
+use core::panic::PanicInfo;
+use core::ptr::NonNull;
+
+use arrayvec::ArrayString;
+use kernel_core::mem::Kernel;
+use kernel_core::mem::Phys;
+use kernel_core::mem::Virt;
+use kernel_core::mem::stack::Stack;
+use kernel_core::print;
+use kernel_core::println;
+
+kernel_core::entry!();
+
+unsafe extern "C" {
+    static __KERNEL_HI: ffi::c_void;
+    static __KERNEL_OFFSET: ffi::c_void;
+}
+
+#[unsafe(link_section = ".text.start")]
+#[unsafe(no_mangle)]
+unsafe extern "C" fn _start_kernel(
+    device_tree: NonNull<device_tree::blob::Header>,
+    tables_lo: u64,
+    tables_hi: u64,
+    initrd_lo: u64,
+    initrd_hi: u64,
+) -> ! {
+    kernel_core::init();
+
+    let device_tree = unsafe { device_tree::Blob::from_ptr(device_tree.cast()) };
+
+    unsafe { kernel_core::mmu::init_kernel() };
+
+    let virt = |address: *const ffi::c_void| Virt::<Kernel>::new(address as u64).to_phys();
+
+    kernel_core::mem::init(
+        &device_tree,
+        unsafe { virt(&__KERNEL_OFFSET)..virt(&__KERNEL_HI) },
+        Phys::new(tables_lo)..Phys::new(tables_hi),
+        Phys::new(initrd_lo)..Phys::new(initrd_hi),
+    );
+
+    print!("stack_overflow::stack_overflow...\t");
+
+    Stack::new()
+        .expect("Failed to allocate kernel stack")
+        .enter(main)
+}
+
+extern "C" fn main() -> ! {
+    stack_overflow(0);
+
+    println!("[failed]");
+    panic!("Execution continued after stack overflow");
+}
+
+#[allow(unconditional_recursion)]
+fn stack_overflow(depth: u64) -> u64 {
+    // Prevent tail-call optimizations
+    hint::black_box(stack_overflow(hint::black_box(depth + 1)))
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let mut message = ArrayString::<256>::new();
+    let _ = write!(message, "{}", info.message());
+
+    if message.contains("stack overflow") {
+        println!("[ok]");
+        kernel_core::spin()
+    }
+
+    println!("[failed]");
+    kernel_core::handle_panic(info)
+}
@@ -0,0 +1,102 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::ffi;
+use core::panic::PanicInfo;
+use core::ptr::NonNull;
+
+use kernel_core::mem::Kernel;
+use kernel_core::mem::Phys;
+use kernel_core::mem::Virt;
+use kernel_core::mem::stack::Stack;
+use kernel_core::print;
+use kernel_core::println;
+use kernel_core::thread;
+use kernel_core::time;
+
+kernel_core::entry!();
+
+unsafe extern "C" {
+    static __KERNEL_HI: ffi::c_void;
+    static __KERNEL_OFFSET: ffi::c_void;
+}
+
+#[unsafe(link_section = ".text.start")]
+#[unsafe(no_mangle)]
+unsafe extern "C" fn _start_kernel(
+    device_tree: NonNull<device_tree::blob::Header>,
+    tables_lo: u64,
+    tables_hi: u64,
+    initrd_lo: u64,
+    initrd_hi: u64,
+) -> ! {
+    kernel_core::init();
+
+    let device_tree = unsafe { device_tree::Blob::from_ptr(device_tree.cast()) };
+
+    unsafe { kernel_core::mmu::init_kernel() };
+
+    let virt = |address: *const ffi::c_void| Virt::<Kernel>::new(address as u64).to_phys();
+
+    kernel_core::mem::init(
+        &device_tree,
+        unsafe { virt(&__KERNEL_OFFSET)..virt(&__KERNEL_HI) },
+        Phys::new(tables_lo)..Phys::new(tables_hi),
+        Phys::new(initrd_lo)..Phys::new(initrd_hi),
+    );
+
+    unsafe { kernel_core::mmu::unmap_identity(kernel_core::mem::alloc::global()) };
+
+    kernel_core::mem::heap::init();
+    kernel_core::smp::start(&device_tree, secondary);
+
+    print!("threads::preemption...\t");
+
+    Stack::new()
+        .expect("Failed to allocate kernel stack")
+        .enter(main)
+}
+
+extern "C" fn main() -> ! {
+    thread::enable_preemption();
+
+    let threads = (0..4u64)
+        .map(|i| {
+            thread::spawn(move || {
+                // Busy wait, relying on preemption to share the core
+                for _ in 0..2 {
+                    time::spin(thread::SLICE * 5);
+                }
+                i * i
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let sum = threads
+        .into_iter()
+        .map(thread::JoinHandle::join)
+        .sum::<u64>();
+
+    match sum {
+        14 => {
+            println!("[ok]");
+            kernel_core::spin()
+        }
+        _ => {
+            println!("[failed]");
+            panic!("Joined threads returned {}", sum);
+        }
+    }
+}
+
+extern "C" fn secondary() -> ! {
+    thread::idle()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kernel_core::handle_panic(info)
+}
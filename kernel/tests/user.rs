@@ -0,0 +1,161 @@
+#![no_std]
+#![no_main]
+
+use core::ffi;
+use core::panic::PanicInfo;
+use core::ptr::NonNull;
+
+use kernel_core::fs;
+use kernel_core::mem::Kernel;
+use kernel_core::mem::PAGE_SIZE;
+use kernel_core::mem::Phys;
+use kernel_core::mem::User;
+use kernel_core::mem::Virt;
+use kernel_core::mem::stack::Stack;
+use kernel_core::mmu;
+use kernel_core::print;
+use kernel_core::println;
+use kernel_core::process::Process;
+use kernel_core::process::Status;
+use kernel_core::syscall;
+use kernel_core::thread;
+
+kernel_core::entry!();
+
+unsafe extern "C" {
+    static __KERNEL_HI: ffi::c_void;
+    static __KERNEL_OFFSET: ffi::c_void;
+    static __USER_LO: u32;
+    static __USER_HI: u32;
+}
+
+#[unsafe(link_section = ".text.start")]
+#[unsafe(no_mangle)]
+unsafe extern "C" fn _start_kernel(
+    device_tree: NonNull<device_tree::blob::Header>,
+    tables_lo: u64,
+    tables_hi: u64,
+    initrd_lo: u64,
+    initrd_hi: u64,
+) -> ! {
+    kernel_core::init();
+
+    let device_tree = unsafe { device_tree::Blob::from_ptr(device_tree.cast()) };
+
+    unsafe { kernel_core::mmu::init_kernel() };
+
+    let virt = |address: *const ffi::c_void| Virt::<Kernel>::new(address as u64).to_phys();
+
+    kernel_core::mem::init(
+        &device_tree,
+        unsafe { virt(&__KERNEL_OFFSET)..virt(&__KERNEL_HI) },
+        Phys::new(tables_lo)..Phys::new(tables_hi),
+        Phys::new(initrd_lo)..Phys::new(initrd_hi),
+    );
+
+    kernel_core::initrd::init(Phys::new(initrd_lo)..Phys::new(initrd_hi));
+
+    unsafe { kernel_core::mmu::unmap_identity(kernel_core::mem::alloc::global()) };
+
+    kernel_core::mem::heap::init();
+    kernel_core::smp::start(&device_tree, secondary);
+
+    Stack::new()
+        .expect("Failed to allocate kernel stack")
+        .enter(main)
+}
+
+extern "C" fn main() -> ! {
+    thread::enable_preemption();
+    fs::init();
+
+    check("user::el0", run_user(), 7);
+    check("user::hello", run_program(&[b"/bin/hello", b"world"]), 0);
+    check("user::cat", run_program(&[b"/bin/cat", b"/", b"/bin"]), 0);
+
+    kernel_core::spin()
+}
+
+fn check(name: &str, status: Status, code: i32) {
+    print!("{}...\t", name);
+    match status == Status::Exited(code) {
+        true => println!("[ok]"),
+        false => {
+            println!("[failed]");
+            panic!("Expected exit code {}, got {:?}", code, status);
+        }
+    }
+}
+
+/// Run the program at `__USER_LO` at EL0, which writes a greeting through a
+/// system call and exits with its first argument.
+fn run_user() -> Status {
+    let process = Process::new().expect("Failed to create process");
+    let code = unsafe {
+        let lo = &__USER_LO as *const u32 as *const u8;
+        let hi = &__USER_HI as *const u32 as *const u8;
+        core::slice::from_raw_parts(lo, hi.offset_from(lo) as usize)
+    };
+
+    let entry = Virt::<User>::new(PAGE_SIZE);
+    process
+        .map(entry, PAGE_SIZE, mmu::Attr::user(true, true, false))
+        .and_then(|()| process.write(entry, code))
+        .and_then(|()| process.protect(entry, PAGE_SIZE, mmu::Attr::user(true, false, true)))
+        .expect("Failed to load user program");
+
+    let stack = process.map_stack().expect("Failed to map user stack");
+    process.start(entry, stack, &[7]);
+    process.wait()
+}
+
+/// Load the executable from the initrd named by `argv[0]` and wait for it to exit.
+fn run_program(argv: &[&[u8]]) -> Status {
+    let path = core::str::from_utf8(argv[0]).unwrap();
+    let image = fs::read(path).expect("Failed to read program");
+
+    let process = Process::new().expect("Failed to create process");
+    process
+        .exec(&image, argv, &[])
+        .expect("Failed to exec program");
+    process.wait()
+}
+
+// Write a greeting to standard output, then exit with the first argument.
+core::arch::global_asm! {
+r#"
+.pushsection .rodata.user, "a"
+.balign 4
+.global __USER_LO
+.global __USER_HI
+__USER_LO:
+    mov x19, x0
+
+    mov x0, {STDOUT}
+    adr x1, 1f
+    mov x2, 2f - 1f
+    mov x8, {WRITE}
+    svc 0
+
+    mov x0, x19
+    mov x8, {EXIT}
+    svc 0
+1:
+    .ascii "Hello from EL0\n"
+2:
+__USER_HI:
+.popsection
+"#,
+    STDOUT = const syscall::fd::STDOUT,
+    WRITE = const syscall::Number::Write as u64,
+    EXIT = const syscall::Number::Exit as u64,
+}
+
+extern "C" fn secondary() -> ! {
+    thread::idle()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kernel_core::handle_panic(info)
+}
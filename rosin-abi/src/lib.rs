@@ -0,0 +1,198 @@
+//! System call interface shared by the kernel and user programs.
+//!
+//! System calls are made from EL0 with `svc #0`. The system call number is
+//! passed in `x8` and up to six arguments in `x0` through `x5`. The result is
+//! returned in `x0`: a non-negative value on success, or a negated [`Error`]
+//! on failure.
+
+#![no_std]
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u64)]
+pub enum Number {
+    /// `read(fd, buffer, len) -> read`: 0 at the end of a file, and blocks
+    /// on the console until at least one byte is read
+    Read = 0,
+    /// `write(fd, buffer, len) -> written`
+    Write = 1,
+    /// `exit(code) -> !`
+    Exit = 2,
+    /// `yield() -> 0`
+    Yield = 3,
+    /// `sleep(nanoseconds) -> 0`
+    Sleep = 4,
+    /// `getpid() -> pid`
+    GetPid = 5,
+    /// `mmap(address, len, protection) -> address`: anonymous, zeroed memory
+    /// at `address`, or anywhere if zero
+    Mmap = 6,
+    /// `munmap(address, len) -> 0`
+    Munmap = 7,
+    /// `open(path, path_len, flags) -> fd`: paths are relative to the root
+    Open = 8,
+    /// `close(fd) -> 0`
+    Close = 9,
+    /// `lseek(fd, offset, whence) -> offset`
+    Lseek = 10,
+    /// `stat(path, path_len, stat) -> 0`, filling in a [`Stat`]
+    Stat = 11,
+    /// `readdir(fd, entry) -> 1`, filling in the next [`DirEntry`], or 0 at
+    /// the end of the directory
+    ReadDir = 12,
+    /// `mkdir(path, path_len) -> 0`
+    Mkdir = 13,
+}
+
+/// Bits of the `protection` argument to [`Number::Mmap`].
+pub mod protection {
+    pub const READ: u64 = 1 << 0;
+    pub const WRITE: u64 = 1 << 1;
+    pub const EXECUTE: u64 = 1 << 2;
+}
+
+/// Standard input, output, and error, which start out as the console.
+pub mod fd {
+    pub const STDIN: u64 = 0;
+    pub const STDOUT: u64 = 1;
+    pub const STDERR: u64 = 2;
+}
+
+/// Bits of the `flags` argument to [`Number::Open`], as in Linux.
+pub mod open {
+    pub const READ_ONLY: u64 = 0o0;
+    pub const WRITE_ONLY: u64 = 0o1;
+    pub const READ_WRITE: u64 = 0o2;
+    /// Mask of the access mode
+    pub const ACCESS: u64 = 0o3;
+    /// Create a regular file if nothing exists at the path
+    pub const CREATE: u64 = 0o100;
+    /// With [`CREATE`], fail if something already exists at the path
+    pub const EXCLUSIVE: u64 = 0o200;
+    pub const TRUNCATE: u64 = 0o1000;
+    /// Write at the end of the file
+    pub const APPEND: u64 = 0o2000;
+    /// Fail unless the path is a directory
+    pub const DIRECTORY: u64 = 0o200000;
+}
+
+/// `whence` argument to [`Number::Lseek`].
+pub mod seek {
+    pub const SET: u64 = 0;
+    pub const CURRENT: u64 = 1;
+    pub const END: u64 = 2;
+}
+
+/// File type bits of [`Stat::mode`] and [`DirEntry::mode`], as in `st_mode`.
+pub mod mode {
+    pub const TYPE: u32 = 0o170000;
+    pub const DIRECTORY: u32 = 0o040000;
+    pub const FILE: u32 = 0o100000;
+    pub const CHARACTER: u32 = 0o020000;
+}
+
+/// Granularity of `mmap` and `munmap`, in bytes.
+pub const PAGE_SIZE: usize = 1 << 16;
+
+/// Longest file name, in bytes.
+pub const NAME_MAX: usize = 255;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct Stat {
+    pub ino: u64,
+    /// File type (see [`mode`]) and permission bits
+    pub mode: u32,
+    pub nlink: u32,
+    pub size: u64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct DirEntry {
+    pub ino: u64,
+    /// File type (see [`mode`]) and permission bits
+    pub mode: u32,
+    /// Bytes of `name` in use
+    pub len: u32,
+    pub name: [u8; NAME_MAX + 1],
+}
+
+impl DirEntry {
+    pub fn name(&self) -> &[u8] {
+        &self.name[..(self.len as usize).min(NAME_MAX)]
+    }
+}
+
+impl Default for DirEntry {
+    fn default() -> Self {
+        Self {
+            ino: 0,
+            mode: 0,
+            len: 0,
+            name: [0; NAME_MAX + 1],
+        }
+    }
+}
+
+/// Failure of a system call, returned negated. Values match Linux's `errno`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u64)]
+pub enum Error {
+    /// No such file or directory
+    NoEntry = 2,
+    /// Invalid executable
+    NoExec = 8,
+    BadFd = 9,
+    NoMemory = 12,
+    /// Invalid user pointer
+    Fault = 14,
+    Exists = 17,
+    NotDirectory = 20,
+    IsDirectory = 21,
+    Invalid = 22,
+    /// Too many open files
+    TooManyFiles = 24,
+    /// No space left on device
+    NoSpace = 28,
+    NameTooLong = 36,
+    NoSys = 38,
+}
+
+impl Error {
+    pub fn from_errno(errno: u64) -> Option<Self> {
+        match errno {
+            2 => Some(Error::NoEntry),
+            8 => Some(Error::NoExec),
+            9 => Some(Error::BadFd),
+            12 => Some(Error::NoMemory),
+            14 => Some(Error::Fault),
+            17 => Some(Error::Exists),
+            20 => Some(Error::NotDirectory),
+            21 => Some(Error::IsDirectory),
+            22 => Some(Error::Invalid),
+            24 => Some(Error::TooManyFiles),
+            28 => Some(Error::NoSpace),
+            36 => Some(Error::NameTooLong),
+            38 => Some(Error::NoSys),
+            _ => None,
+        }
+    }
+}
+
+/// Encode a system call result into `x0`.
+pub fn encode(result: Result<u64, Error>) -> u64 {
+    match result {
+        Ok(value) => value,
+        Err(error) => (error as u64).wrapping_neg(),
+    }
+}
+
+/// Decode a system call result from `x0`.
+pub fn decode(value: u64) -> Result<u64, Error> {
+    // Errors occupy the last page of the address space, which is never
+    // returned as a user address
+    match value.wrapping_neg() {
+        errno @ 1..4096 => Err(Error::from_errno(errno).unwrap_or(Error::NoSys)),
+        _ => Ok(value),
+    }
+}
@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+use core::str;
+
+use rosin_user::Writer;
+use rosin_user::abi::mode;
+use rosin_user::abi::open;
+use rosin_user::eprintln;
+use rosin_user::println;
+use rosin_user::sys;
+
+rosin_user::entry!(main);
+
+/// Print each file named by the arguments, or list it if it is a directory.
+fn main() -> i32 {
+    let mut status = 0;
+
+    for path in rosin_user::args().skip(1) {
+        let Ok(path) = str::from_utf8(path) else {
+            eprintln!("cat: invalid path");
+            status = 1;
+            continue;
+        };
+
+        if let Err(error) = cat(path) {
+            eprintln!("cat: {}: {:?}", path, error);
+            status = 1;
+        }
+    }
+
+    status
+}
+
+fn cat(path: &str) -> Result<(), rosin_user::abi::Error> {
+    let stat = sys::stat(path)?;
+    let fd = sys::open(path, open::READ_ONLY)?;
+
+    if stat.mode & mode::TYPE == mode::DIRECTORY {
+        while let Some(entry) = sys::readdir(fd)? {
+            let slash = match entry.mode & mode::TYPE == mode::DIRECTORY {
+                true => "/",
+                false => "",
+            };
+            println!("{}{}", str::from_utf8(entry.name()).unwrap_or("?"), slash);
+        }
+    } else {
+        let mut buffer = [0u8; 512];
+        loop {
+            match sys::read(fd, &mut buffer)? {
+                0 => break,
+                read => Writer::stdout().write_all(&buffer[..read])?,
+            }
+        }
+    }
+
+    sys::close(fd)
+}
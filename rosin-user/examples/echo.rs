@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+
+use rosin_user::Writer;
+use rosin_user::abi::fd;
+use rosin_user::sys;
+
+rosin_user::entry!(main);
+
+/// End of transmission, sent by Ctrl-D.
+const EOT: u8 = 0x04;
+
+/// Print arguments separated by spaces, or copy standard input to standard
+/// output until Ctrl-D if there are none.
+fn main() -> i32 {
+    let mut stdout = Writer::stdout();
+
+    if rosin_user::args().count() > 1 {
+        for (index, arg) in rosin_user::args().skip(1).enumerate() {
+            if index > 0 {
+                let _ = stdout.write_all(b" ");
+            }
+            let _ = stdout.write_all(arg);
+        }
+        let _ = stdout.write_all(b"\n");
+        return 0;
+    }
+
+    let mut buffer = [0u8; 64];
+    loop {
+        let read = match sys::read(fd::STDIN, &mut buffer) {
+            Ok(read) => read,
+            Err(_) => return 1,
+        };
+
+        let line = &buffer[..read];
+        let end = line.iter().position(|byte| *byte == EOT);
+        let _ = stdout.write_all(&line[..end.unwrap_or(read)]);
+
+        if end.is_some() {
+            return 0;
+        }
+    }
+}
@@ -0,0 +1,24 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use rosin_user::println;
+
+rosin_user::entry!(main);
+
+fn main() -> i32 {
+    let args = rosin_user::args()
+        .map(String::from_utf8_lossy)
+        .collect::<Vec<_>>();
+
+    println!(
+        "Hello from process {}: {:?}",
+        rosin_user::sys::getpid(),
+        args
+    );
+    0
+}
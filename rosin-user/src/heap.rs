@@ -0,0 +1,124 @@
+use core::alloc::GlobalAlloc;
+use core::alloc::Layout;
+use core::cell::UnsafeCell;
+use core::ptr::NonNull;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering;
+
+use rosin_abi::PAGE_SIZE;
+use rosin_abi::protection;
+
+use crate::sys;
+
+#[global_allocator]
+static HEAP: Heap = Heap {
+    lock: AtomicBool::new(false),
+    classes: UnsafeCell::new([None; CLASSES.len()]),
+};
+
+/// Allocations up to the largest size class are carved out of shared slab
+/// pages; anything larger is mapped directly with `mmap`.
+const CLASSES: [usize; 10] = [16, 32, 64, 128, 256, 512, 1024, 2048, 4096, 8192];
+
+/// Heap on top of anonymous memory from [`sys::mmap`].
+struct Heap {
+    lock: AtomicBool,
+    /// Free list per size class
+    classes: UnsafeCell<[Option<NonNull<Object>>; CLASSES.len()]>,
+}
+
+// SAFETY: free lists are only accessed while holding `lock`
+unsafe impl Sync for Heap {}
+
+struct Object {
+    next: Option<NonNull<Object>>,
+}
+
+impl Heap {
+    fn class(layout: Layout) -> Option<usize> {
+        // Objects are naturally aligned within page-aligned slabs
+        let size = layout.size().max(layout.align());
+        CLASSES.iter().position(|class| size <= *class)
+    }
+
+    fn with<T>(&self, f: impl FnOnce(&mut [Option<NonNull<Object>>; CLASSES.len()]) -> T) -> T {
+        while self
+            .lock
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+
+        let value = f(unsafe { &mut *self.classes.get() });
+        self.lock.store(false, Ordering::Release);
+        value
+    }
+
+    fn map(pages: usize) -> Option<NonNull<u8>> {
+        let len = pages * PAGE_SIZE;
+        let pointer = unsafe {
+            sys::mmap(
+                core::ptr::null_mut(),
+                len,
+                protection::READ | protection::WRITE,
+            )
+        };
+        pointer.ok().and_then(NonNull::new)
+    }
+}
+
+unsafe impl GlobalAlloc for Heap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if layout.align() > PAGE_SIZE {
+            return core::ptr::null_mut();
+        }
+
+        let Some(class) = Self::class(layout) else {
+            return Self::map(layout.size().div_ceil(PAGE_SIZE))
+                .map_or(core::ptr::null_mut(), NonNull::as_ptr);
+        };
+
+        self.with(|classes| {
+            if classes[class].is_none() {
+                let slab = Self::map(1)?;
+
+                for offset in (0..PAGE_SIZE).step_by(CLASSES[class]).rev() {
+                    let object = unsafe { slab.byte_add(offset) }.cast::<Object>();
+                    unsafe {
+                        object.write(Object {
+                            next: classes[class],
+                        });
+                    }
+                    classes[class] = Some(object);
+                }
+            }
+
+            let object = classes[class]?;
+            classes[class] = unsafe { object.as_ref().next };
+            Some(object.cast::<u8>())
+        })
+        .map_or(core::ptr::null_mut(), NonNull::as_ptr)
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        let Some(class) = Self::class(layout) else {
+            let len = layout.size().next_multiple_of(PAGE_SIZE);
+            unsafe { sys::munmap(pointer, len) }.expect("Failed to unmap allocation");
+            return;
+        };
+
+        let object = NonNull::new(pointer)
+            .expect("Deallocating null pointer")
+            .cast::<Object>();
+
+        self.with(|classes| {
+            unsafe {
+                object.write(Object {
+                    next: classes[class],
+                });
+            }
+            classes[class] = Some(object);
+        })
+    }
+}
@@ -0,0 +1,98 @@
+//! Runtime for user programs, which are statically linked with `user.ld`
+//! and loaded by the kernel's ELF loader.
+//!
+//! ```ignore
+//! #![no_std]
+//! #![no_main]
+//!
+//! rosin_user::entry!(main);
+//!
+//! fn main() -> i32 {
+//!     rosin_user::println!("Hello from {}", rosin_user::sys::getpid());
+//!     0
+//! }
+//! ```
+
+#![no_std]
+
+extern crate alloc;
+
+use core::ffi::CStr;
+use core::ffi::c_char;
+use core::panic::PanicInfo;
+use core::sync::atomic::AtomicPtr;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+
+pub use rosin_abi as abi;
+
+mod heap;
+mod print;
+pub mod sys;
+
+#[doc(hidden)]
+pub use print::_print;
+pub use print::Writer;
+pub use sys::exit;
+
+/// Define the program's entry point, which runs `main` and exits with the
+/// status it returns.
+#[macro_export]
+macro_rules! entry {
+    ($main:path) => {
+        #[unsafe(no_mangle)]
+        extern "C" fn _start(
+            argc: usize,
+            argv: *const *const core::ffi::c_char,
+            envp: *const *const core::ffi::c_char,
+        ) -> ! {
+            $crate::start(argc, argv, envp, $main)
+        }
+    };
+}
+
+static ARGC: AtomicUsize = AtomicUsize::new(0);
+static ARGV: AtomicPtr<*const c_char> = AtomicPtr::new(core::ptr::null_mut());
+static ENVP: AtomicPtr<*const c_char> = AtomicPtr::new(core::ptr::null_mut());
+
+#[doc(hidden)]
+pub fn start(
+    argc: usize,
+    argv: *const *const c_char,
+    envp: *const *const c_char,
+    main: fn() -> i32,
+) -> ! {
+    ARGC.store(argc, Ordering::Relaxed);
+    ARGV.store(argv.cast_mut(), Ordering::Relaxed);
+    ENVP.store(envp.cast_mut(), Ordering::Relaxed);
+    exit(main())
+}
+
+/// Command line arguments, including the program name.
+pub fn args() -> impl Iterator<Item = &'static [u8]> {
+    let argv = ARGV.load(Ordering::Relaxed);
+    (0..ARGC.load(Ordering::Relaxed)).map(move |index| unsafe { string(*argv.add(index)) })
+}
+
+/// Environment variables, as `KEY=VALUE`.
+pub fn env() -> impl Iterator<Item = &'static [u8]> {
+    let envp = ENVP.load(Ordering::Relaxed);
+    (0..).map_while(move |index| match envp.is_null() {
+        true => None,
+        false => {
+            let pointer = unsafe { *envp.add(index) };
+            (!pointer.is_null()).then(|| unsafe { string(pointer) })
+        }
+    })
+}
+
+/// SAFETY: `pointer` must point to a null-terminated string on the initial stack.
+unsafe fn string(pointer: *const c_char) -> &'static [u8] {
+    unsafe { CStr::from_ptr(pointer) }.to_bytes()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    eprintln!("[{}] {}", sys::getpid(), info);
+    exit(101)
+}
@@ -0,0 +1,74 @@
+use core::fmt;
+use core::fmt::Write as _;
+
+use rosin_abi::fd;
+
+use crate::sys;
+
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => {
+        $crate::_print($crate::abi::fd::STDOUT, format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => {
+        {
+            $crate::print!($($arg)*);
+            $crate::print!("\n");
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => {
+        $crate::_print($crate::abi::fd::STDERR, format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! eprintln {
+    () => ($crate::eprint!("\n"));
+    ($($arg:tt)*) => {
+        {
+            $crate::eprint!($($arg)*);
+            $crate::eprint!("\n");
+        }
+    };
+}
+
+/// Writer for a file descriptor, retrying short writes.
+pub struct Writer(pub u64);
+
+impl Writer {
+    pub fn stdout() -> Self {
+        Self(fd::STDOUT)
+    }
+
+    pub fn stderr() -> Self {
+        Self(fd::STDERR)
+    }
+
+    pub fn write_all(&mut self, mut bytes: &[u8]) -> Result<(), rosin_abi::Error> {
+        while !bytes.is_empty() {
+            let written = sys::write(self.0, bytes)?;
+            bytes = &bytes[written..];
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Write for Writer {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        self.write_all(string.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+#[doc(hidden)]
+pub fn _print(fd: u64, args: fmt::Arguments) {
+    let _ = Writer(fd).write_fmt(args);
+}
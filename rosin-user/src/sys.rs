@@ -0,0 +1,126 @@
+//! Thin wrappers around each system call in [`rosin_abi::Number`].
+
+use core::arch::asm;
+use core::time::Duration;
+
+use rosin_abi::DirEntry;
+use rosin_abi::Error;
+use rosin_abi::Number;
+use rosin_abi::Stat;
+
+/// Make system call `number` with `args`.
+///
+/// # Safety
+///
+/// Caller must uphold the requirements of the system call, e.g. that
+/// pointers are valid for the given lengths.
+pub unsafe fn syscall(number: Number, args: [u64; 6]) -> Result<u64, Error> {
+    let mut x0 = args[0];
+    unsafe {
+        asm!(
+            "svc 0",
+            inout("x0") x0,
+            in("x1") args[1],
+            in("x2") args[2],
+            in("x3") args[3],
+            in("x4") args[4],
+            in("x5") args[5],
+            in("x8") number as u64,
+            options(nostack),
+        );
+    }
+    rosin_abi::decode(x0)
+}
+
+/// Read into `buffer`, blocking until at least one byte is available.
+pub fn read(fd: u64, buffer: &mut [u8]) -> Result<usize, Error> {
+    let args = [fd, buffer.as_mut_ptr() as u64, buffer.len() as u64, 0, 0, 0];
+    unsafe { syscall(Number::Read, args) }.map(|read| read as usize)
+}
+
+pub fn write(fd: u64, buffer: &[u8]) -> Result<usize, Error> {
+    let args = [fd, buffer.as_ptr() as u64, buffer.len() as u64, 0, 0, 0];
+    unsafe { syscall(Number::Write, args) }.map(|written| written as usize)
+}
+
+/// End the process with exit status `code`.
+pub fn exit(code: i32) -> ! {
+    let _ = unsafe { syscall(Number::Exit, [code as u64, 0, 0, 0, 0, 0]) };
+    unreachable!("Returned from exit")
+}
+
+pub fn yield_now() {
+    let _ = unsafe { syscall(Number::Yield, [0; 6]) };
+}
+
+pub fn sleep(duration: Duration) {
+    let nanoseconds = duration.as_nanos().min(u64::MAX as u128) as u64;
+    let _ = unsafe { syscall(Number::Sleep, [nanoseconds, 0, 0, 0, 0, 0]) };
+}
+
+pub fn getpid() -> u64 {
+    unsafe { syscall(Number::GetPid, [0; 6]) }.expect("getpid failed")
+}
+
+/// Map `len` bytes of zeroed memory with `protection` (see
+/// [`rosin_abi::protection`]) at `address`, or anywhere if null.
+///
+/// # Safety
+///
+/// The mapping must not overlap memory in use, including the program itself.
+pub unsafe fn mmap(address: *mut u8, len: usize, protection: u64) -> Result<*mut u8, Error> {
+    let args = [address as u64, len as u64, protection, 0, 0, 0];
+    unsafe { syscall(Number::Mmap, args) }.map(|address| address as *mut u8)
+}
+
+/// Unmap `len` bytes of memory at `address`.
+///
+/// # Safety
+///
+/// Nothing may refer to the unmapped memory afterward.
+pub unsafe fn munmap(address: *mut u8, len: usize) -> Result<(), Error> {
+    let args = [address as u64, len as u64, 0, 0, 0, 0];
+    unsafe { syscall(Number::Munmap, args) }.map(drop)
+}
+
+/// Open `path` with `flags` (see [`rosin_abi::open`]), returning a file
+/// descriptor.
+pub fn open(path: &str, flags: u64) -> Result<u64, Error> {
+    let args = [path.as_ptr() as u64, path.len() as u64, flags, 0, 0, 0];
+    unsafe { syscall(Number::Open, args) }
+}
+
+pub fn close(fd: u64) -> Result<(), Error> {
+    unsafe { syscall(Number::Close, [fd, 0, 0, 0, 0, 0]) }.map(drop)
+}
+
+/// Move the offset of `fd` relative to `whence` (see [`rosin_abi::seek`]),
+/// returning the new offset.
+pub fn lseek(fd: u64, offset: i64, whence: u64) -> Result<u64, Error> {
+    unsafe { syscall(Number::Lseek, [fd, offset as u64, whence, 0, 0, 0]) }
+}
+
+pub fn stat(path: &str) -> Result<Stat, Error> {
+    let mut stat = Stat::default();
+    let args = [
+        path.as_ptr() as u64,
+        path.len() as u64,
+        &mut stat as *mut Stat as u64,
+        0,
+        0,
+        0,
+    ];
+    unsafe { syscall(Number::Stat, args) }.map(|_| stat)
+}
+
+/// Next entry of the directory open at `fd`, or `None` past the last.
+pub fn readdir(fd: u64) -> Result<Option<DirEntry>, Error> {
+    let mut entry = DirEntry::default();
+    let args = [fd, &mut entry as *mut DirEntry as u64, 0, 0, 0, 0];
+    unsafe { syscall(Number::ReadDir, args) }.map(|more| (more > 0).then_some(entry))
+}
+
+pub fn mkdir(path: &str) -> Result<(), Error> {
+    let args = [path.as_ptr() as u64, path.len() as u64, 0, 0, 0, 0];
+    unsafe { syscall(Number::Mkdir, args) }.map(drop)
+}